@@ -0,0 +1,100 @@
+//! Feeds random and mutated NARG strings to the FRI verifier, and random bytes to the proof decoders,
+//! none of which may panic. Run with `cargo +nightly fuzz run fri_verifier` from the repository root.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use spongefish::{CommonUnitToBytes, DomainSeparator};
+use stark_tools::{commitable::{Commitable, LeafOrdering}, fields::Field192, merkletree::{MerkleCap, PedersenTreeConfig}, polynomial::{Polynomial, PolynomialCoefficient}};
+use stark_tools::proximityproofs::{cap_to_bytes, FRIProtocol, FriConfig, FriProof, ProximityProofDomainSeparator, ProximityProofVerifier, QueryMode};
+use std::sync::OnceLock;
+
+type Field = Field192;
+type G = ark_ed_on_bls12_381::EdwardsProjective;
+type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+
+/// A valid proof for each leaf ordering and query mode, to mutate.
+struct Fixture {
+    fri: FRIProtocol<Field, G, H>,
+    commitment: MerkleCap,
+    proof: Vec<u8>,
+}
+
+fn fixtures() -> &'static [Fixture] {
+    static FIXTURES: OnceLock<Vec<Fixture>> = OnceLock::new();
+    FIXTURES.get_or_init(|| {
+        let mut rng = ark_std::test_rng();
+        let pedersen_config = PedersenTreeConfig::new(&mut rng);
+        let mut fixtures = vec![];
+        for leaf_ordering in [LeafOrdering::Natural, LeafOrdering::BitReversed] {
+            for query_mode in [QueryMode::PerRound, QueryMode::Propagated] {
+                let config = FriConfig::new(16, 4, 2, vec![2; 3], 1).expect("invalid FRI config")
+                    .with_pow_bits(2)
+                    .with_query_mode(query_mode);
+                let fri = FRIProtocol::<Field, G, H>::new(config.clone(), pedersen_config.clone())
+                    .expect("unsupported FRI config")
+                    .with_leaf_ordering(leaf_ordering);
+                let commited_poly = PolynomialCoefficient::<Field>::random_poly(&mut rng, config.degree_bound - 1)
+                    .fft(config.blowup).expect("no evaluation domain")
+                    .commit_with_ordering(&pedersen_config, leaf_ordering).expect("failed to commit to the polynomial");
+                let commitment = commited_poly.ptree.cap();
+
+                let io: DomainSeparator<H> = fri.new_pp_proof();
+                let mut prover_state = io.to_prover_state();
+                prover_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+                prover_state.ratchet().unwrap();
+                fri.prove_proof(&mut prover_state, &commited_poly).expect("FRI proof generation failed");
+                let proof = prover_state.narg_string().to_vec();
+                fixtures.push(Fixture { fri, commitment, proof });
+            }
+        }
+        fixtures
+    })
+}
+
+/// Runs the verifier on `proof`; it may reject, but must not panic.
+fn verify(fixture: &Fixture, proof: &[u8]) {
+    let io: DomainSeparator<H> = fixture.fri.new_pp_proof();
+    let mut verifier_state = io.to_verifier_state(proof);
+    if verifier_state.public_bytes(&cap_to_bytes(&fixture.commitment)).is_err() || verifier_state.ratchet().is_err() {
+        return;
+    }
+    let _ = fixture.fri.verify(&mut verifier_state, &fixture.commitment);
+}
+
+// The first byte picks a fixture and whether the rest of the input is a proof of its own
+// or a list of edits to the fixture's valid proof.
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, data)) = data.split_first() else {
+        return;
+    };
+    let fixtures = fixtures();
+    let fixture = &fixtures[selector as usize % fixtures.len()];
+
+    let proof = if selector & 0x80 == 0 {
+        data.to_vec()
+    } else {
+        // each edit is 3 bytes: a position in the proof and a mask to XOR into it;
+        // a trailing partial edit truncates the proof or extends it with its bytes
+        let mut proof = fixture.proof.clone();
+        let mut edits = data.chunks_exact(3);
+        for edit in edits.by_ref() {
+            let position = u16::from_le_bytes([edit[0], edit[1]]) as usize % proof.len();
+            proof[position] ^= edit[2];
+        }
+        match edits.remainder() {
+            [] => {}
+            [cut] => proof.truncate(proof.len() - (*cut as usize).min(proof.len())),
+            tail => proof.extend_from_slice(tail),
+        }
+        proof
+    };
+    verify(fixture, &proof);
+
+    if let Ok(parsed) = fixture.fri.parse_proof(&proof) {
+        let _ = fixture.fri.proof_to_narg(&parsed);
+    }
+    let _ = FriProof::<Field>::from_bytes(data);
+    if let Ok(json) = std::str::from_utf8(data) {
+        let _ = FriProof::<Field>::from_json(json);
+    }
+});
@@ -0,0 +1,237 @@
+use ark_crypto_primitives::crh::TwoToOneCRHScheme;
+use ark_ff::PrimeField;
+
+use crate::error::StarkToolsResult;
+use crate::merkletree::{merkle_error, PedersenTreeConfig, Root, TwoToOneHash};
+
+/// An append-only Merkle tree over a growing log, shaped like the RFC 6962 log tree.
+/// The tree over `n` leaves splits at the largest power of two below `n`, so every
+/// earlier root stays reachable and two roots can be proven consistent.
+/// Leaves are hashed with the leaf parameters and nodes with the two-to-one parameters,
+/// which keeps leaves and inner nodes apart. Caps and padding do not apply here.
+#[derive(Clone)]
+pub struct AppendOnlyMerkleTree {
+    pedersen_config: PedersenTreeConfig,
+    /// `levels[h][i]` is the root of the complete subtree over leaves `i*2^h .. (i+1)*2^h`.
+    levels: Vec<Vec<Root>>,
+}
+
+/// Proves that a leaf is at `leaf_index` in the tree of the first `tree_size` leaves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InclusionProof {
+    pub leaf_index: usize,
+    pub tree_size: usize,
+    /// Sibling subtree roots, from the leaf up.
+    pub path: Vec<Root>,
+}
+
+/// Proves that the tree of `old_size` leaves is a prefix of the tree of `new_size` leaves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConsistencyProof {
+    pub old_size: usize,
+    pub new_size: usize,
+    pub path: Vec<Root>,
+}
+
+/// The largest power of two strictly below `n`, for `n > 1`.
+fn split_point(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+fn hash_nodes(pedersen_config: &PedersenTreeConfig, left: &Root, right: &Root) -> StarkToolsResult<Root> {
+    Ok(<TwoToOneHash as TwoToOneCRHScheme>::compress(&pedersen_config.two_to_one_crh_params, left, right)?)
+}
+
+impl AppendOnlyMerkleTree {
+    pub fn new(pedersen_config: &PedersenTreeConfig) -> Self {
+        Self {
+            pedersen_config: pedersen_config.clone(),
+            levels: vec![vec![]],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends `leaf` and returns its index.
+    pub fn push<F: PrimeField>(&mut self, leaf: F) -> StarkToolsResult<usize> {
+        let digest: Root = self.pedersen_config.hash_leaf(leaf)?;
+        self.levels[0].push(digest);
+
+        // every level that now ends in a full pair gains a parent
+        let mut height = 0;
+        while self.levels[height].len().is_multiple_of(2) {
+            let level = &self.levels[height];
+            let parent = hash_nodes(&self.pedersen_config, &level[level.len() - 2], &level[level.len() - 1])?;
+            if self.levels.len() == height + 1 {
+                self.levels.push(vec![]);
+            }
+            self.levels[height + 1].push(parent);
+            height += 1;
+        }
+        Ok(self.len() - 1)
+    }
+
+    /// The current root, `None` while the log is empty.
+    pub fn root(&self) -> Option<Root> {
+        self.root_at(self.len()).ok()
+    }
+
+    /// The root the tree had when it held the first `tree_size` leaves.
+    pub fn root_at(&self, tree_size: usize) -> StarkToolsResult<Root> {
+        if tree_size == 0 || tree_size > self.len() {
+            return Err(merkle_error(format!("no root for {tree_size} leaves, the log holds {}", self.len())));
+        }
+        self.subtree_root(0, tree_size)
+    }
+
+    /// Root of the subtree over leaves `start..start + size`, as split by the log tree.
+    fn subtree_root(&self, start: usize, size: usize) -> StarkToolsResult<Root> {
+        if size.is_power_of_two() && start.is_multiple_of(size) {
+            let height = size.trailing_zeros() as usize;
+            return Ok(self.levels[height][start >> height]);
+        }
+        let k = split_point(size);
+        let left = self.subtree_root(start, k)?;
+        let right = self.subtree_root(start + k, size - k)?;
+        hash_nodes(&self.pedersen_config, &left, &right)
+    }
+
+    /// Proves leaf `leaf_index` against the root of the first `tree_size` leaves.
+    pub fn prove_inclusion(&self, leaf_index: usize, tree_size: usize) -> StarkToolsResult<InclusionProof> {
+        if leaf_index >= tree_size || tree_size > self.len() {
+            return Err(merkle_error(format!("leaf {leaf_index} is not in a tree of {tree_size} leaves")));
+        }
+        let mut path = vec![];
+        self.inclusion_path(leaf_index, 0, tree_size, &mut path)?;
+        Ok(InclusionProof { leaf_index, tree_size, path })
+    }
+
+    fn inclusion_path(&self, leaf_index: usize, start: usize, size: usize, path: &mut Vec<Root>) -> StarkToolsResult<()> {
+        if size == 1 {
+            return Ok(());
+        }
+        let k = split_point(size);
+        if leaf_index < start + k {
+            self.inclusion_path(leaf_index, start, k, path)?;
+            path.push(self.subtree_root(start + k, size - k)?);
+        } else {
+            self.inclusion_path(leaf_index, start + k, size - k, path)?;
+            path.push(self.subtree_root(start, k)?);
+        }
+        Ok(())
+    }
+
+    /// Proves that the tree of `old_size` leaves is a prefix of the tree of `new_size` leaves.
+    pub fn prove_consistency(&self, old_size: usize, new_size: usize) -> StarkToolsResult<ConsistencyProof> {
+        if old_size == 0 || old_size > new_size || new_size > self.len() {
+            return Err(merkle_error(format!("cannot prove {old_size} leaves consistent with {new_size}")));
+        }
+        let mut path = vec![];
+        self.consistency_path(old_size, 0, new_size, true, &mut path)?;
+        Ok(ConsistencyProof { old_size, new_size, path })
+    }
+
+    /// `old_size` counts leaves from `start`; `whole` is true while the old tree is the
+    /// left-most subtree whose root the verifier already knows.
+    fn consistency_path(&self, old_size: usize, start: usize, size: usize, whole: bool, path: &mut Vec<Root>) -> StarkToolsResult<()> {
+        if old_size == size {
+            if !whole {
+                path.push(self.subtree_root(start, size)?);
+            }
+            return Ok(());
+        }
+        let k = split_point(size);
+        if old_size <= k {
+            self.consistency_path(old_size, start, k, whole, path)?;
+            path.push(self.subtree_root(start + k, size - k)?);
+        } else {
+            self.consistency_path(old_size - k, start + k, size - k, false, path)?;
+            path.push(self.subtree_root(start, k)?);
+        }
+        Ok(())
+    }
+}
+
+impl InclusionProof {
+    /// Checks `leaf` against `root`, the root of the first `self.tree_size` leaves.
+    pub fn verify<F: PrimeField>(&self, pedersen_config: &PedersenTreeConfig, root: &Root, leaf: F) -> StarkToolsResult<bool> {
+        if self.leaf_index >= self.tree_size {
+            return Ok(false);
+        }
+        let mut index = self.leaf_index;
+        let mut last = self.tree_size - 1;
+        let mut node: Root = pedersen_config.hash_leaf(leaf)?;
+        for sibling in &self.path {
+            if last == 0 {
+                return Ok(false);
+            }
+            if index & 1 == 1 || index == last {
+                node = hash_nodes(pedersen_config, sibling, &node)?;
+                // a right-most node without a sibling is carried up unchanged
+                while index & 1 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                node = hash_nodes(pedersen_config, &node, sibling)?;
+            }
+            index >>= 1;
+            last >>= 1;
+        }
+        Ok(last == 0 && node == *root)
+    }
+}
+
+impl ConsistencyProof {
+    /// Checks that `old_root` (over `self.old_size` leaves) is a prefix of `new_root` (over `self.new_size`).
+    pub fn verify(&self, pedersen_config: &PedersenTreeConfig, old_root: &Root, new_root: &Root) -> StarkToolsResult<bool> {
+        if self.old_size == 0 || self.old_size > self.new_size {
+            return Ok(false);
+        }
+        if self.old_size == self.new_size {
+            return Ok(self.path.is_empty() && old_root == new_root);
+        }
+        if self.path.is_empty() {
+            return Ok(false);
+        }
+
+        // when the old tree is a complete subtree its root is the starting node
+        let mut path = self.path.clone();
+        if self.old_size.is_power_of_two() {
+            path.insert(0, *old_root);
+        }
+        let mut index = self.old_size - 1;
+        let mut last = self.new_size - 1;
+        while index & 1 == 1 {
+            index >>= 1;
+            last >>= 1;
+        }
+
+        let mut old_node = path[0];
+        let mut new_node = path[0];
+        for sibling in &path[1..] {
+            if last == 0 {
+                return Ok(false);
+            }
+            if index & 1 == 1 || index == last {
+                old_node = hash_nodes(pedersen_config, sibling, &old_node)?;
+                new_node = hash_nodes(pedersen_config, sibling, &new_node)?;
+                while index & 1 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                new_node = hash_nodes(pedersen_config, &new_node, sibling)?;
+            }
+            index >>= 1;
+            last >>= 1;
+        }
+        Ok(old_node == *old_root && new_node == *new_root && last == 0)
+    }
+}
@@ -1,61 +1,226 @@
-use ark_ff::PrimeField;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-use crate::{merkletree::{new_pedersen_merkletree, PedersenMerkleTree, PedersenTreeConfig}, point::Point, polynomial::PolynomialPoints};
+use ark_ff::{BigInteger, PrimeField};
+use bimap::BiHashMap;
+
+use crate::{error::{StarkToolsError, StarkToolsResult}, merkletree::{new_pedersen_merkletree, PedersenMerkleTree, PedersenTreeConfig}, point::Point, polynomial::PolynomialPoints};
+
+/// How the evaluations of a polynomial are laid out over the Merkle leaves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeafOrdering {
+    /// Leaf `i` holds the evaluation at `omega^i`.
+    Natural,
+    /// Leaf `bitrev(i)` holds the evaluation at `omega^i`.
+    /// `f(w)` and `f(-w)` become Merkle siblings, so one path opens both.
+    BitReversed,
+}
+
+impl LeafOrdering {
+    /// The leaf holding the evaluation at `omega^index` in a tree of `num_leaves` leaves.
+    pub fn leaf_position(&self, index: u64, num_leaves: u64) -> u64 {
+        match self {
+            LeafOrdering::Natural => index,
+            LeafOrdering::BitReversed => bit_reverse(index, num_leaves.trailing_zeros()),
+        }
+    }
+}
+
+/// Reverses the lowest `bits` bits of `index`.
+pub fn bit_reverse(index: u64, bits: u32) -> u64 {
+    if bits == 0 {
+        return 0;
+    }
+    index.reverse_bits() >> (64 - bits)
+}
 
 pub struct Commited<T> {
     pub data: T,
-    pub ptree: PedersenMerkleTree
+    pub ptree: PedersenMerkleTree,
+    pub ordering: LeafOrdering
 }
 
 impl<T> Commited<T> {
     pub fn new(ptree: PedersenMerkleTree, data: T ) -> Commited<T> {
         Commited {
             data,
-            ptree
+            ptree,
+            ordering: LeafOrdering::Natural
         }
     }
+
+    /// The leaf holding the evaluation at `omega^index`.
+    pub fn leaf_position(&self, index: u64) -> u64 {
+        self.ordering.leaf_position(index, self.ptree.capacity() as u64)
+    }
+
+    /// Stores the Merkle tree at `path`, see `PedersenMerkleTree::save`.
+    pub fn save_tree<P: AsRef<Path>>(&self, path: P) -> StarkToolsResult<()> {
+        self.ptree.save(path)
+    }
+
+    /// Reattaches `data` to a tree stored with `save_tree`, mapping it from disk instead of rebuilding it.
+    /// `ordering` must be the leaf ordering `data` was committed with.
+    /// Committed evaluations can be stored along with the tree instead, see `save` and `resume`.
+    pub fn load<P: AsRef<Path>>(path: P, pedersen_config: &PedersenTreeConfig, data: T, ordering: LeafOrdering) -> StarkToolsResult<Commited<T>> {
+        Ok(Commited {
+            data,
+            ptree: PedersenMerkleTree::open(path, pedersen_config)?,
+            ordering
+        })
+    }
+}
+
+const EVALUATIONS_FILE_MAGIC: &[u8; 4] = b"STEV";
+const EVALUATIONS_FILE_VERSION: u32 = 1;
+
+/// Where `Commited::save` stores the evaluations committed in the tree at `path`.
+pub fn evaluations_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut path = path.as_ref().as_os_str().to_owned();
+    path.push(".evals");
+    path.into()
+}
+
+impl<F: PrimeField> Commited<PolynomialPoints<F>> {
+    /// Stores the tree at `path` and the evaluations at `evaluations_path(path)`, in domain order,
+    /// so that a prover can `resume` from disk alone.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> StarkToolsResult<()> {
+        let roots = self.data.roots_preimage.as_ref()
+            .ok_or_else(|| StarkToolsError::InvalidDomain("the points are not evaluations over a subgroup".to_string()))?;
+        let mut file = BufWriter::new(File::create(evaluations_path(&path))?);
+        file.write_all(EVALUATIONS_FILE_MAGIC)?;
+        file.write_all(&EVALUATIONS_FILE_VERSION.to_le_bytes())?;
+        let ordering = match self.ordering {
+            LeafOrdering::Natural => 0,
+            LeafOrdering::BitReversed => 1,
+        };
+        for value in [self.data.degree, ordering, roots.len() as u64] {
+            file.write_all(&value.to_le_bytes())?;
+        }
+        for i in 0..roots.len() as u64 {
+            let y = roots.get_by_right(&i)
+                .and_then(|x| self.data.points.get(x))
+                .ok_or_else(|| StarkToolsError::InvalidDomain(format!("no evaluation at the root of unity {i}")))?
+                .get_y();
+            let mut bytes = y.into_bigint().to_bytes_le();
+            bytes.resize(field_bytes::<F>(), 0);
+            file.write_all(&bytes)?;
+        }
+        file.flush()?;
+        self.ptree.save(path)
+    }
+
+    /// Maps the tree stored by `save` at `path` and reads back the evaluations committed in it.
+    /// `pedersen_config` must be the configuration the tree was built with.
+    pub fn resume<P: AsRef<Path>>(path: P, pedersen_config: &PedersenTreeConfig) -> StarkToolsResult<Self> {
+        let invalid = |message: &str| StarkToolsError::Io(io::Error::new(io::ErrorKind::InvalidData, message.to_string()));
+        let ptree = PedersenMerkleTree::open(&path, pedersen_config)?;
+        let bytes = fs::read(evaluations_path(&path))?;
+
+        let mut reader = &bytes[..];
+        let mut read_u64 = || -> StarkToolsResult<u64> {
+            let (value, rest) = reader.split_first_chunk::<8>().ok_or_else(|| invalid("truncated evaluations file"))?;
+            reader = rest;
+            Ok(u64::from_le_bytes(*value))
+        };
+        let header = read_u64()?.to_le_bytes();
+        if &header[..4] != EVALUATIONS_FILE_MAGIC || header[4..] != EVALUATIONS_FILE_VERSION.to_le_bytes() {
+            return Err(invalid("not an evaluations file"));
+        }
+        let degree = read_u64()?;
+        let ordering = match read_u64()? {
+            0 => LeafOrdering::Natural,
+            1 => LeafOrdering::BitReversed,
+            _ => return Err(invalid("unknown leaf ordering")),
+        };
+        let num_points = read_u64()?;
+        if num_points != ptree.num_leaves() as u64 || degree >= num_points {
+            return Err(invalid("the evaluations do not match the merkle tree"));
+        }
+        let values = reader.chunks_exact(field_bytes::<F>());
+        if values.len() as u64 != num_points || !values.remainder().is_empty() {
+            return Err(invalid("evaluations file of the wrong length"));
+        }
+
+        let omega = F::get_root_of_unity(num_points).ok_or(StarkToolsError::MissingRootOfUnity { order: num_points })?;
+        let mut points = HashMap::new();
+        let mut roots_preimage = BiHashMap::new();
+        let mut root = F::ONE;
+        for (i, y) in values.map(F::from_le_bytes_mod_order).enumerate() {
+            roots_preimage.insert(root, i as u64);
+            points.insert(root, Box::new(Point::new(root, y)));
+            root *= omega;
+        }
+        Ok(Commited {
+            data: PolynomialPoints { degree, points, roots_preimage: Some(roots_preimage) },
+            ptree,
+            ordering
+        })
+    }
+}
+
+/// Number of bytes a field element takes in an evaluations file.
+fn field_bytes<F: PrimeField>() -> usize {
+    F::BigInt::NUM_LIMBS * 8
 }
 
 pub trait Commitable<F: PrimeField>
 where Self: Sized {
-    fn commit(self, pedersen_config: &PedersenTreeConfig) -> Commited<Self>;
+    fn commit(self, pedersen_config: &PedersenTreeConfig) -> StarkToolsResult<Commited<Self>> {
+        self.commit_with_ordering(pedersen_config, LeafOrdering::Natural)
+    }
+    fn commit_with_ordering(self, pedersen_config: &PedersenTreeConfig, ordering: LeafOrdering) -> StarkToolsResult<Commited<Self>>;
 }
 
 impl <F: PrimeField> Commitable<F> for PolynomialPoints<F> {
-    fn commit(self, pedersen_config: &PedersenTreeConfig) -> Commited<Self> {
+    /// Fails unless the points are the evaluations over a subgroup of roots of unity, as `fft` produces them.
+    fn commit_with_ordering(self, pedersen_config: &PedersenTreeConfig, ordering: LeafOrdering) -> StarkToolsResult<Commited<Self>> {
         // let leaf_hash_param = sha256::Sha256::T1::setup(rng);
         let mut points_vectorized = self.points.clone()
             .into_iter().collect::<Vec<(F, Box<Point<F>>)>>();
-        let roots = &self.roots_preimage;
+        let roots = self.roots_preimage.as_ref()
+            .ok_or_else(|| StarkToolsError::InvalidDomain("the points are not evaluations over a subgroup".to_string()))?;
+        if let Some((x, _)) = points_vectorized.iter().find(|p| roots.get_by_left(&p.0).is_none()) {
+            return Err(StarkToolsError::InvalidDomain(format!("the point {x} is not in the evaluation domain")));
+        }
         // println!("{:?}", roots);
         points_vectorized.sort_by(|p1, p2| 
-            roots.as_ref()
-            .unwrap()
+            roots
             .get_by_left(&p1.0)
             .cmp(
-                &roots.as_ref()
-                .unwrap()
+                &roots
                 .get_by_left(&p2.0)
             )
         );
-        let list = points_vectorized.iter().map(|p| p.1.get_y()).collect::<Vec<F>>();
+        let mut list = points_vectorized.iter().map(|p| p.1.get_y()).collect::<Vec<F>>();
+        if ordering == LeafOrdering::BitReversed {
+            let num_leaves = list.len() as u64;
+            let natural = list.clone();
+            natural.into_iter().enumerate().for_each(|(i, y)| {
+                list[ordering.leaf_position(i as u64, num_leaves) as usize] = y;
+            });
+        }
         // println!("listt: {:?}", list.iter()
             // .map(|x| x.into_bigint().to_bytes_be()).collect::<Vec<Vec<u8>>>()
         // );
+        // evaluation domains are powers of two, so no padding is involved
         let ptree = new_pedersen_merkletree(
             pedersen_config,
             list
-        );
+        )?;
         // let tree = MerkleTree::blank(sha256::Sha256 as TwoToOneCRHScheme, sha256::Sha256::setup(rng), 10);
         // let tree = MerkleTree::new::<BigInt<N>>(
         //     &(), 
         //     &(),
         //     x
         // ).unwrap();
-        Commited {
+        Ok(Commited {
             data: self,
-            ptree
-        }
+            ptree,
+            ordering
+        })
         // todo!()
     }
 }
\ No newline at end of file
@@ -0,0 +1,238 @@
+use spongefish::{CommonUnitToBytes, DomainSeparator};
+use stark_tools::{commitable::{Commitable, LeafOrdering}, fields::Field192, merkletree::{MerkleCap, PedersenTreeConfig}, polynomial::{Polynomial, PolynomialCoefficient}};
+use stark_tools::proximityproofs::{cap_to_bytes, narg_length, FRIProtocol, FriConfig, FriProof, PolynomialCommitmentScheme, ProximityProofDomainSeparator, ProximityProofVerifier, QueryMode};
+
+type Field = Field192;
+
+pub fn fri_test(pedersen_config: &PedersenTreeConfig) {
+    // Instantiate the group and the random oracle:
+    // Set the group:
+    type G = ark_ed_on_bls12_381::EdwardsProjective;
+    // type G = Projective;
+    // Set the hash function (commented out other valid choices):
+    // type H = spongefish::hash::Keccak;
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+    // type H = spongefish::hash::legacy::DigestBridge<sha2::Sha256>;
+
+    const LEAF_ORDERING: LeafOrdering = LeafOrdering::BitReversed;
+    let config = FriConfig::new(32, 8, 4, vec![3; 2], 1).expect("invalid FRI config").with_pow_bits(8);
+
+    let fri = FRIProtocol::<Field, G, H>::new(
+        config.clone(),
+        pedersen_config.clone()
+    ).expect("unsupported FRI config").with_leaf_ordering(LEAF_ORDERING);
+    let io: DomainSeparator<H> = fri.new_pp_proof();
+
+    let mut rnd = ark_std::test_rng();
+
+    let mut prover_state = io.to_prover_state();
+    let commited_poly = 
+        PolynomialCoefficient::<Field>::random_poly(&mut rnd, config.degree_bound - 1).fft(config.blowup).expect("no evaluation domain")
+        .commit_with_ordering(pedersen_config, LEAF_ORDERING).expect("failed to commit to the polynomial");
+
+
+    prover_state.public_bytes(&cap_to_bytes(&commited_poly.ptree.cap())).unwrap();
+    prover_state.ratchet().unwrap();
+
+    let typed_proof = fri.prove_proof(&mut prover_state, &commited_poly).expect("FRI proof generation faild!");
+    let proof = prover_state.narg_string();
+
+    // Print out the hex-encoded FRI proof.
+    println!("FRI Proof:\n{}", hex::encode(proof));
+
+    // Round trip the typed proof through both encodings and write it back as a NARG string.
+    let decoded = FriProof::<Field>::from_bytes(&typed_proof.to_bytes()).expect("failed to decode the binary FRI proof");
+    let decoded = FriProof::<Field>::from_json(&decoded.to_json()).expect("failed to decode the JSON FRI proof");
+    assert_eq!(decoded, typed_proof);
+    println!("FRI proof with {} fold commitments and {} remainder coefficients, {} bytes as JSON",
+        decoded.fold_commitments.len(), decoded.final_polynomial.len(), decoded.to_json().len());
+    let narg = fri.proof_to_narg(&decoded).expect("FRI proof does not match the config");
+    assert_eq!(narg, proof);
+
+    // Verify the proof: create the verifier transcript, add the statement to it, and invoke the verifier.
+    let mut verifier_state = io.to_verifier_state(&narg);
+    verifier_state.public_bytes(&cap_to_bytes(&commited_poly.ptree.cap())).unwrap();
+    verifier_state.ratchet().unwrap();
+
+    fri.verify(&mut verifier_state, &commited_poly.ptree.cap()).and_then(|_| {println!("FRI proof successfully verified!"); Ok(())}).expect("Invalid proof");
+}
+
+pub fn fri_batch_test(pedersen_config: &PedersenTreeConfig) {
+    type G = ark_ed_on_bls12_381::EdwardsProjective;
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+
+    const LEAF_ORDERING: LeafOrdering = LeafOrdering::BitReversed;
+    // a trace-like polynomial with the full degree bound and two smaller ones joining in later rounds
+    const DEGREE_BOUNDS: [u64; 3] = [16, 8, 4];
+    let config = FriConfig::new(16, 8, 2, vec![3; 3], 1).expect("invalid FRI config");
+
+    let fri = FRIProtocol::<Field, G, H>::new(
+        config.clone(),
+        pedersen_config.clone()
+    ).expect("unsupported FRI config").with_leaf_ordering(LEAF_ORDERING);
+    let io: DomainSeparator<H> = fri.new_batch_pp_proof(&DEGREE_BOUNDS).expect("degree bounds do not match the folding rounds");
+    assert!(fri.new_batch_pp_proof(&[16, 6]).is_err());
+
+    let mut rnd = ark_std::test_rng();
+    let commited_polys: Vec<_> = DEGREE_BOUNDS.iter().map(|degree_bound| 
+        PolynomialCoefficient::<Field>::random_poly(&mut rnd, degree_bound - 1).fft(config.blowup).expect("no evaluation domain")
+        .commit_with_ordering(pedersen_config, LEAF_ORDERING).expect("failed to commit to the polynomial")
+    ).collect();
+    let commitments: Vec<MerkleCap> = commited_polys.iter().map(|p| p.ptree.cap()).collect();
+
+    let mut prover_state = io.to_prover_state();
+    for commitment in &commitments {
+        prover_state.public_bytes(&cap_to_bytes(commitment)).unwrap();
+    }
+    prover_state.ratchet().unwrap();
+    let proof = fri.prove_batch(&mut prover_state, &commited_polys).expect("batched FRI proof generation faild!");
+    println!("Batched FRI Proof of {} bytes", proof.len());
+
+    let mut verifier_state = io.to_verifier_state(proof);
+    for commitment in &commitments {
+        verifier_state.public_bytes(&cap_to_bytes(commitment)).unwrap();
+    }
+    verifier_state.ratchet().unwrap();
+    fri.verify_batch(&mut verifier_state, &commitments, &DEGREE_BOUNDS).expect("Invalid proof");
+    println!("Batched FRI proof successfully verified!");
+}
+
+pub fn fri_deep_test(pedersen_config: &PedersenTreeConfig) {
+    type G = ark_ed_on_bls12_381::EdwardsProjective;
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+
+    const LEAF_ORDERING: LeafOrdering = LeafOrdering::BitReversed;
+    let config = FriConfig::new(16, 8, 2, vec![3; 3], 1).expect("invalid FRI config");
+
+    let fri = FRIProtocol::<Field, G, H>::new(
+        config.clone(),
+        pedersen_config.clone()
+    ).expect("unsupported FRI config").with_leaf_ordering(LEAF_ORDERING);
+    let io: DomainSeparator<H> = fri.new_deep_proof();
+
+    let mut rnd = ark_std::test_rng();
+    let poly = PolynomialCoefficient::<Field>::random_poly(&mut rnd, config.degree_bound - 1);
+    let commited_poly = poly.clone().fft(config.blowup).expect("no evaluation domain")
+        .commit_with_ordering(pedersen_config, LEAF_ORDERING).expect("failed to commit to the polynomial");
+    let commitment = commited_poly.ptree.cap();
+
+    let mut prover_state = io.to_prover_state();
+    prover_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    prover_state.ratchet().unwrap();
+    let opening = fri.prove_deep(&mut prover_state, &commited_poly).expect("DEEP-FRI proof generation faild!");
+    assert_eq!(opening.value, poly.evaluate(opening.point));
+    let proof = prover_state.narg_string();
+    println!("DEEP-FRI Proof of {} bytes", proof.len());
+
+    let mut verifier_state = io.to_verifier_state(proof);
+    verifier_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    verifier_state.ratchet().unwrap();
+    let verified = fri.verify_deep(&mut verifier_state, &commitment).expect("Invalid proof");
+    assert_eq!(verified, opening);
+    println!("DEEP-FRI opening successfully verified!");
+}
+
+pub fn fri_pcs_test(pedersen_config: &PedersenTreeConfig) {
+    type G = ark_ed_on_bls12_381::EdwardsProjective;
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+    type Pcs = FRIProtocol<Field, G, H>;
+
+    const NUM_POINTS: usize = 3;
+    let config = FriConfig::new(16, 8, 2, vec![3; 3], 1).expect("invalid FRI config");
+    let pcs = Pcs::new(config, pedersen_config.clone()).expect("unsupported FRI config")
+        .with_leaf_ordering(LeafOrdering::BitReversed);
+    let io: DomainSeparator<H> = pcs.add_opening(pcs.add_commitment(DomainSeparator::new("FRI polynomial commitment")).ratchet(), NUM_POINTS);
+
+    let mut rnd = ark_std::test_rng();
+    // below the degree bound, so that the committer pads it
+    let poly = PolynomialCoefficient::<Field>::random_poly(&mut rnd, 10);
+    let points: Vec<Field> = (0..NUM_POINTS).map(|_| ark_std::UniformRand::rand(&mut rnd)).collect();
+    let (commitment, committer_state) = pcs.commit(poly.clone()).expect("polynomial above the degree bound");
+
+    let mut prover_state = io.to_prover_state();
+    prover_state.public_bytes(&pcs.commitment_to_bytes(&commitment)).unwrap();
+    prover_state.ratchet().unwrap();
+    let values = pcs.open(&mut prover_state, &committer_state, &points).expect("FRI opening proof generation faild!");
+    assert!(points.iter().zip(&values).all(|(&point, &value)| poly.evaluate(point) == value));
+    let proof = prover_state.narg_string();
+    println!("FRI opening proof at {} points of {} bytes", NUM_POINTS, proof.len());
+
+    let mut verifier_state = io.to_verifier_state(proof);
+    verifier_state.public_bytes(&pcs.commitment_to_bytes(&commitment)).unwrap();
+    verifier_state.ratchet().unwrap();
+    pcs.verify_opening(&mut verifier_state, &commitment, &points, &values).expect("Invalid proof");
+    println!("FRI opening successfully verified!");
+}
+
+/// Runs single, batched and DEEP proofs over a grid of configurations and checks that each proof
+/// has exactly the length its domain separator describes and verifies.
+pub fn fri_transcript_test(pedersen_config: &PedersenTreeConfig) {
+    type G = ark_ed_on_bls12_381::EdwardsProjective;
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+
+    let configs = [
+        FriConfig::new(16, 2, 2, vec![2; 3], 1),
+        FriConfig::new(32, 8, 2, vec![3, 2, 1, 1], 1),
+        FriConfig::new(64, 4, 4, vec![2; 2], 3),
+        FriConfig::new(16, 4, 4, vec![3], 3),
+    ];
+    let mut rnd = ark_std::test_rng();
+    let mut checked = 0;
+    for config in configs {
+        let config = config.expect("invalid FRI config");
+        // grinding only adds two messages before the query seed, once per round or once in all
+        for (leaf_ordering, (query_mode, pow_bits)) in [LeafOrdering::Natural, LeafOrdering::BitReversed].into_iter()
+            .flat_map(|ordering| [(QueryMode::PerRound, 2), (QueryMode::Propagated, 0)].into_iter().map(move |mode| (ordering, mode))) {
+            let config = config.clone().with_query_mode(query_mode).with_pow_bits(pow_bits);
+            let fri = FRIProtocol::<Field, G, H>::new(config.clone(), pedersen_config.clone())
+                .expect("unsupported FRI config").with_leaf_ordering(leaf_ordering);
+            let commit = |rnd: &mut _, degree_bound: u64| {
+                PolynomialCoefficient::<Field>::random_poly(rnd, degree_bound - 1).fft(config.blowup).expect("no evaluation domain")
+                    .commit_with_ordering(pedersen_config, leaf_ordering).expect("failed to commit to the polynomial")
+            };
+
+            let mut degree_bounds = vec![config.degree_bound];
+            if config.num_rounds() > 1 {
+                degree_bounds.push(config.degree_bound / config.folding_factor);
+            }
+            for degree_bounds in [&degree_bounds[..1], &degree_bounds[..]] {
+                let polys: Vec<_> = degree_bounds.iter().map(|&degree_bound| commit(&mut rnd, degree_bound)).collect();
+                let commitments: Vec<MerkleCap> = polys.iter().map(|p| p.ptree.cap()).collect();
+                let io: DomainSeparator<H> = fri.new_batch_pp_proof(degree_bounds).expect("degree bounds do not match the folding rounds");
+                let mut prover_state = io.to_prover_state();
+                for commitment in &commitments {
+                    prover_state.public_bytes(&cap_to_bytes(commitment)).unwrap();
+                }
+                prover_state.ratchet().unwrap();
+                let proof = fri.prove_batch(&mut prover_state, &polys).expect("FRI proof generation faild!");
+                let expected = narg_length(&fri.batch_schema(degree_bounds).expect("degree bounds do not match the folding rounds"));
+                assert_eq!(proof.len(), expected, "{config:?} with {leaf_ordering:?} leaves and degree bounds {degree_bounds:?}");
+
+                let mut verifier_state = io.to_verifier_state(proof);
+                for commitment in &commitments {
+                    verifier_state.public_bytes(&cap_to_bytes(commitment)).unwrap();
+                }
+                verifier_state.ratchet().unwrap();
+                fri.verify_batch(&mut verifier_state, &commitments, degree_bounds).expect("Invalid proof");
+                checked += 1;
+            }
+
+            let poly = commit(&mut rnd, config.degree_bound);
+            let commitment = poly.ptree.cap();
+            let io: DomainSeparator<H> = fri.new_deep_proof();
+            let mut prover_state = io.to_prover_state();
+            prover_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+            prover_state.ratchet().unwrap();
+            fri.prove_deep(&mut prover_state, &poly).expect("DEEP-FRI proof generation faild!");
+            let proof = prover_state.narg_string();
+            assert_eq!(proof.len(), narg_length(&fri.deep_schema()), "DEEP-FRI {config:?} with {leaf_ordering:?} leaves");
+
+            let mut verifier_state = io.to_verifier_state(proof);
+            verifier_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+            verifier_state.ratchet().unwrap();
+            fri.verify_deep(&mut verifier_state, &commitment).expect("Invalid proof");
+            checked += 1;
+        }
+    }
+    println!("FRI transcripts match their domain separators in {checked} proofs");
+}
@@ -0,0 +1,33 @@
+use spongefish::{CommonUnitToBytes, DomainSeparator};
+use stark_tools::{fields::Field192, merkletree::PedersenTreeConfig, polynomial::{Polynomial, PolynomialCoefficient}};
+use stark_tools::proximityproofs::{cap_to_bytes, LigeroConfig, LigeroProtocol, ProximityProofDomainSeparator, ProximityProofVerifier};
+
+type Field = Field192;
+
+pub fn ligero_test(pedersen_config: &PedersenTreeConfig) {
+    type G = ark_ed_on_bls12_381::EdwardsProjective;
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+
+    // a 16 x 16 coefficient matrix encoded into 16 x 64 entries
+    let config = LigeroConfig::new(256, 16, 4, 32).expect("invalid Ligero config");
+    let ligero = LigeroProtocol::<Field, G, H>::new(config.clone(), pedersen_config.clone()).expect("unsupported Ligero config");
+    let io: DomainSeparator<H> = ligero.new_pp_proof();
+
+    let mut rnd = ark_std::test_rng();
+    let poly = PolynomialCoefficient::<Field>::random_poly(&mut rnd, config.degree_bound - 1);
+    let commited_poly = ligero.commit(poly).expect("failed to commit to the polynomial");
+    let commitment = commited_poly.ptree.cap();
+
+    let mut prover_state = io.to_prover_state();
+    prover_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    prover_state.ratchet().unwrap();
+    ligero.prove_commitment(&mut prover_state, &commited_poly).expect("Ligero proof generation faild!");
+    let proof = prover_state.narg_string();
+    println!("Ligero proof of {} bytes", proof.len());
+
+    let mut verifier_state = io.to_verifier_state(proof);
+    verifier_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    verifier_state.ratchet().unwrap();
+    ligero.verify(&mut verifier_state, &commitment).expect("Invalid proof");
+    println!("Ligero proof successfully verified!");
+}
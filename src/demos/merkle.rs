@@ -0,0 +1,48 @@
+use stark_tools::{append_only_merkletree::AppendOnlyMerkleTree, commitable::{evaluations_path, Commitable, Commited, LeafOrdering}, fields::Field192, merkletree::PedersenTreeConfig, polynomial::{Polynomial, PolynomialCoefficient, PolynomialPoints}};
+
+type Field = Field192;
+
+/// Grows a log, then checks an inclusion proof against an earlier root and a consistency proof between two roots.
+pub fn append_only_test(pedersen_config: &PedersenTreeConfig) {
+    const OLD_SIZE: usize = 5;
+    const NEW_SIZE: usize = 11;
+
+    let mut log = AppendOnlyMerkleTree::new(pedersen_config);
+    for i in 0..NEW_SIZE as u64 {
+        log.push(Field::from(i)).expect("failed to append to the log");
+    }
+    let old_root = log.root_at(OLD_SIZE).expect("no root for the old tree");
+    let new_root = log.root().expect("the log is empty");
+
+    let inclusion = log.prove_inclusion(3, OLD_SIZE).expect("failed to prove inclusion");
+    assert!(inclusion.verify(pedersen_config, &old_root, Field::from(3)).expect("failed to verify inclusion"));
+    assert!(!inclusion.verify(pedersen_config, &old_root, Field::from(4)).expect("failed to verify inclusion"));
+
+    let consistency = log.prove_consistency(OLD_SIZE, NEW_SIZE).expect("failed to prove consistency");
+    assert!(consistency.verify(pedersen_config, &old_root, &new_root).expect("failed to verify consistency"));
+    assert!(!consistency.verify(pedersen_config, &new_root, &old_root).expect("failed to verify consistency"));
+    println!("Append-only log of {NEW_SIZE} leaves: inclusion and consistency proofs successfully verified!");
+}
+
+/// Stores a commitment, resumes it from disk alone and opens a leaf of the resumed tree against the original cap.
+pub fn persistence_test(pedersen_config: &PedersenTreeConfig) {
+    let mut rnd = ark_std::test_rng();
+    let commited_poly = PolynomialCoefficient::<Field>::random_poly(&mut rnd, 63).fft(4).expect("no evaluation domain")
+        .commit_with_ordering(pedersen_config, LeafOrdering::BitReversed).expect("failed to commit to the polynomial");
+    let path = std::env::temp_dir().join(format!("stark-tools-demo-{}.tree", std::process::id()));
+    commited_poly.save(&path).expect("failed to store the commitment");
+
+    let resumed = Commited::<PolynomialPoints<Field>>::resume(&path, pedersen_config).expect("failed to resume the commitment");
+    assert_eq!(resumed.ptree.cap(), commited_poly.ptree.cap());
+    assert_eq!(resumed.ordering, commited_poly.ordering);
+    assert!(commited_poly.data.points.iter().all(|(x, point)| resumed.data.points.get(x).map(|p| p.get_y()) == Some(point.get_y())));
+
+    let index = 37;
+    let leaf = resumed.data.points.iter()
+        .find(|(x, _)| resumed.data.roots_preimage.as_ref().and_then(|roots| roots.get_by_left(*x)) == Some(&index))
+        .map(|(_, point)| point.get_y()).expect("no evaluation at the queried root");
+    let opening = resumed.ptree.generate_proof(resumed.leaf_position(index) as usize).expect("failed to open the resumed tree");
+    assert!(pedersen_config.verify_path(opening, &commited_poly.ptree.cap(), leaf).expect("failed to verify the opening"));
+    std::fs::remove_file(evaluations_path(&path)).and_then(|_| std::fs::remove_file(&path)).expect("failed to remove the stored commitment");
+    println!("Commitment of {} evaluations resumed from disk and successfully opened!", resumed.ptree.num_leaves());
+}
@@ -0,0 +1,8 @@
+//! Demos run by the binary. Each one proves and verifies with a protocol of the library and prints what it did.
+pub mod merkle;
+pub mod fri;
+pub mod stir;
+pub mod whir;
+pub mod ligero;
+pub mod schnorr;
+pub mod sigma;
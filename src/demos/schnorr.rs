@@ -0,0 +1,50 @@
+use ark_ec::PrimeGroup;
+use spongefish::codecs::arkworks_algebra::DomainSeparator;
+use stark_tools::spongefish_schnorr::{batch_verify, keygen, new_schnorr_proof, new_schnorr_signature, prove, sign, verify, verify_signature};
+
+#[allow(non_snake_case)]
+pub fn spongefish_test() {
+    // Instantiate the group and the random oracle:
+    // Set the group:
+    type G = ark_ed_on_bls12_381::EdwardsProjective;
+    // Set the hash function (commented out other valid choices):
+    // type H = spongefish::hash::Keccak;
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+    // type H = spongefish::hash::legacy::DigestBridge<sha2::Sha256>;
+
+    // Set up the IO for the protocol transcript with domain separator "spongefish::examples::schnorr"
+    let io: DomainSeparator<H> = new_schnorr_proof::<G, H>("spongefish::example");
+
+    // Set up the elements to prove
+    let P = G::generator();
+
+    let mut rnd = ark_std::test_rng();
+    let (x, X) = keygen::<G, _>(&mut rnd);
+
+    // Invoke the prover, which adds the statement to the transcript.
+    let proof = prove(&io, P, x).expect("Invalid proof");
+
+    // Print out the hex-encoded schnorr proof.
+    println!("Here's a Schnorr proof:\n{}", hex::encode(&proof));
+
+    // Verify the proof, then check that trailing bytes are not accepted along with it.
+    verify(&io, P, X, &proof).expect("Invalid proof");
+    assert!(verify(&io, P, X, &[proof.as_slice(), b"junk"].concat()).is_err());
+
+    // Batch-verify proofs for several keys, then check that a proof under the wrong key spoils the batch.
+    let keys: Vec<(<G as PrimeGroup>::ScalarField, G)> = (0..4).map(|_| keygen::<G, _>(&mut rnd)).collect();
+    let proofs: Vec<Vec<u8>> = keys.iter().map(|(x, _)| prove(&io, P, *x).expect("Invalid proof")).collect();
+    let mut instances: Vec<(&[u8], G)> = proofs.iter().zip(&keys).map(|(proof, (_, X))| (proof.as_slice(), *X)).collect();
+    batch_verify(&io, &mut rnd, P, &instances).expect("Invalid batch");
+    instances[0].1 = keys[1].1;
+    assert!(batch_verify(&io, &mut rnd, P, &instances).is_err());
+    println!("Batch of {} Schnorr proofs successfully verified!", proofs.len());
+
+    let io: DomainSeparator<H> = new_schnorr_signature::<G, H>("spongefish::example::signature");
+    let message = b"a message of any length";
+    let signature = sign(&io, P, x, message).expect("Signing failed");
+    verify_signature(&io, P, X, message, &signature).expect("Invalid signature");
+    assert!(verify_signature(&io, P, X, b"another message", &signature).is_err());
+    assert!(verify_signature(&io, P, X, message, &[signature.as_slice(), b"junk"].concat()).is_err());
+    println!("Schnorr signature successfully verified!");
+}
@@ -0,0 +1,75 @@
+use ark_ec::CurveGroup;
+use ark_ff::UniformRand;
+use spongefish::codecs::arkworks_algebra::{
+    CommonGroupToUnit, DomainSeparator, DuplexSpongeInterface, FieldDomainSeparator, FieldToUnitDeserialize,
+    FieldToUnitSerialize, GroupDomainSeparator, GroupToUnitDeserialize, GroupToUnitSerialize, ProofError,
+    ProofResult, ProverState, UnitToField, VerifierState
+};
+use stark_tools::sigma::{prove, verify, And, Dleq, Okamoto, Or, OrWitness, Schnorr, Scalar, SigmaDomainSeparator, SigmaProtocol};
+
+/// Proves and verifies `protocol` for `witness`, and checks that a simulated transcript verifies too.
+fn prove_and_verify<P, H>(name: &str, protocol: &P, witness: &P::Witness) -> ProofResult<usize>
+where
+    P: SigmaProtocol,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<P::Group> + FieldDomainSeparator<Scalar<P>>,
+    ProverState<H>: GroupToUnitSerialize<P::Group> + UnitToField<Scalar<P>> + FieldToUnitSerialize<Scalar<P>>
+        + CommonGroupToUnit<P::Group>,
+    for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<P::Group>
+        + FieldToUnitDeserialize<Scalar<P>>
+        + UnitToField<Scalar<P>>
+        + CommonGroupToUnit<P::Group>,
+{
+    let io: DomainSeparator<H> = SigmaDomainSeparator::<P>::new_sigma_proof(name, protocol);
+    let mut prover_state = io.to_prover_state();
+    prover_state.public_points(&protocol.statement())?;
+    prover_state.ratchet()?;
+    let proof = prove(&mut prover_state, protocol, witness)?;
+
+    let mut verifier_state = io.to_verifier_state(proof);
+    verifier_state.public_points(&protocol.statement())?;
+    verifier_state.ratchet()?;
+    verify(&mut verifier_state, protocol)?;
+
+    let mut rnd = ark_std::test_rng();
+    let challenge = Scalar::<P>::rand(&mut rnd);
+    let (commitment, response) = protocol.simulate(challenge, &mut rnd);
+    if !protocol.verify(&commitment, challenge, &response) {
+        return Err(ProofError::InvalidProof);
+    }
+    Ok(proof.len())
+}
+
+fn sigma_curve_test<G: CurveGroup>(curve: &str) {
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+
+    let mut rnd = ark_std::test_rng();
+    let g = G::generator();
+    let h = g * G::ScalarField::rand(&mut rnd);
+    let x = G::ScalarField::rand(&mut rnd);
+    let (m, r) = (G::ScalarField::rand(&mut rnd), G::ScalarField::rand(&mut rnd));
+
+    let schnorr = Schnorr { generator: g, public_key: g * x };
+    let dleq = Dleq { g, h, g_x: g * x, h_x: h * x };
+    let okamoto = Okamoto { g, h, commitment: g * m + h * r };
+    // a key whose secret nobody here knows
+    let other = Schnorr { generator: g, public_key: h };
+
+    let sizes = [
+        prove_and_verify::<_, H>("sigma::dleq", &dleq, &x),
+        prove_and_verify::<_, H>("sigma::okamoto", &okamoto, &(m, r)),
+        prove_and_verify::<_, H>("sigma::and", &And(dleq, okamoto), &(x, (m, r))),
+        prove_and_verify::<_, H>("sigma::or", &Or(schnorr, other), &OrWitness::Left(x)),
+        prove_and_verify::<_, H>("sigma::or", &Or(other, schnorr), &OrWitness::Right(x)),
+    ].map(|size| size.expect("Invalid proof"));
+
+    // the honest response to a false statement does not verify
+    let wrong = Dleq { h_x: h * m, ..dleq };
+    assert!(prove_and_verify::<_, H>("sigma::dleq", &wrong, &x).is_err());
+    println!("Sigma proofs over {curve} successfully verified! DLEQ, Okamoto, AND, OR (left), OR (right): {:?} bytes", sizes);
+}
+
+pub fn sigma_test() {
+    sigma_curve_test::<ark_ed_on_bls12_381::EdwardsProjective>("Jubjub");
+    sigma_curve_test::<ark_curve25519::EdwardsProjective>("curve25519");
+}
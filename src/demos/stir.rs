@@ -0,0 +1,50 @@
+use spongefish::{CommonUnitToBytes, DomainSeparator};
+use stark_tools::{commitable::{Commitable, LeafOrdering}, fields::Field192, merkletree::PedersenTreeConfig, polynomial::{Polynomial, PolynomialCoefficient}};
+use stark_tools::proximityproofs::{cap_to_bytes, FRIProtocol, FriConfig, ProximityProofDomainSeparator, ProximityProofProver, ProximityProofVerifier, STIRProtocol, StirConfig};
+
+type Field = Field192;
+
+/// Proves the same committed polynomial with FRI and with STIR at matching query budgets and compares the proof sizes.
+pub fn stir_test(pedersen_config: &PedersenTreeConfig) {
+    type G = ark_ed_on_bls12_381::EdwardsProjective;
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+
+    const LEAF_ORDERING: LeafOrdering = LeafOrdering::BitReversed;
+    // about 24 bits from queries: 2 bits each at rate 1/4, while STIR's rate improves to 1/8 and 1/16
+    let fri_config = FriConfig::new(256, 4, 4, vec![12; 3], 3).expect("invalid FRI config");
+    let stir_config = StirConfig::new(256, 4, 4, vec![12, 8, 6], 3).expect("invalid STIR config");
+
+    let fri = FRIProtocol::<Field, G, H>::new(fri_config.clone(), pedersen_config.clone())
+        .expect("unsupported FRI config").with_leaf_ordering(LEAF_ORDERING);
+    let stir = STIRProtocol::<Field, G, H>::new(stir_config.clone(), pedersen_config.clone())
+        .expect("unsupported STIR config").with_leaf_ordering(LEAF_ORDERING);
+
+    let mut rnd = ark_std::test_rng();
+    let commited_poly = PolynomialCoefficient::<Field>::random_poly(&mut rnd, stir_config.degree_bound - 1)
+        .fft(stir_config.blowup).expect("no evaluation domain")
+        .commit_with_ordering(pedersen_config, LEAF_ORDERING).expect("failed to commit to the polynomial");
+    let commitment = commited_poly.ptree.cap();
+
+    let fri_io: DomainSeparator<H> = fri.new_pp_proof();
+    let mut prover_state = fri_io.to_prover_state();
+    prover_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    prover_state.ratchet().unwrap();
+    let fri_proof = fri.prove(&mut prover_state, &commited_poly).expect("FRI proof generation faild!").to_vec();
+    let mut verifier_state = fri_io.to_verifier_state(&fri_proof);
+    verifier_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    verifier_state.ratchet().unwrap();
+    fri.verify(&mut verifier_state, &commitment).expect("Invalid FRI proof");
+
+    let stir_io: DomainSeparator<H> = stir.new_pp_proof();
+    let mut prover_state = stir_io.to_prover_state();
+    prover_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    prover_state.ratchet().unwrap();
+    let stir_proof = stir.prove(&mut prover_state, &commited_poly).expect("STIR proof generation faild!").to_vec();
+    let mut verifier_state = stir_io.to_verifier_state(&stir_proof);
+    verifier_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    verifier_state.ratchet().unwrap();
+    stir.verify(&mut verifier_state, &commitment).expect("Invalid STIR proof");
+
+    println!("STIR proof successfully verified!");
+    println!("Proof sizes for degree {}: FRI {} bytes, STIR {} bytes", stir_config.degree_bound, fri_proof.len(), stir_proof.len());
+}
@@ -0,0 +1,41 @@
+use spongefish::{CommonUnitToBytes, DomainSeparator};
+use stark_tools::{commitable::{Commitable, LeafOrdering}, fields::Field192, merkletree::PedersenTreeConfig, polynomial::{Polynomial, PolynomialCoefficient}};
+use stark_tools::proximityproofs::{cap_to_bytes, WHIRProtocol, WhirConfig};
+
+type Field = Field192;
+
+pub fn whir_test(pedersen_config: &PedersenTreeConfig) {
+    type G = ark_ed_on_bls12_381::EdwardsProjective;
+    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
+
+    const LEAF_ORDERING: LeafOrdering = LeafOrdering::BitReversed;
+    const NUM_POINTS: usize = 2;
+    // a multilinear polynomial in 8 variables, two of them folded per round
+    let config = WhirConfig::new(256, 4, 4, vec![12, 8, 6], 3).expect("invalid WHIR config");
+    let whir = WHIRProtocol::<Field, G, H>::new(config.clone(), pedersen_config.clone())
+        .expect("unsupported WHIR config").with_leaf_ordering(LEAF_ORDERING);
+    let io: DomainSeparator<H> = whir.new_evaluation_proof(NUM_POINTS);
+
+    let mut rnd = ark_std::test_rng();
+    let poly = PolynomialCoefficient::<Field>::random_poly(&mut rnd, config.degree_bound - 1);
+    let points: Vec<Vec<Field>> = (0..NUM_POINTS).map(|_|
+        (0..config.degree_bound.trailing_zeros()).map(|_| ark_std::UniformRand::rand(&mut rnd)).collect()
+    ).collect();
+    let commited_poly = poly.clone().fft(config.blowup).expect("no evaluation domain")
+        .commit_with_ordering(pedersen_config, LEAF_ORDERING).expect("failed to commit to the polynomial");
+    let commitment = commited_poly.ptree.cap();
+
+    let mut prover_state = io.to_prover_state();
+    prover_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    prover_state.ratchet().unwrap();
+    let values = whir.prove_evaluations(&mut prover_state, &commited_poly, &points).expect("WHIR proof generation faild!");
+    assert!(points.iter().zip(&values).all(|(point, value)| poly.evaluate_multilinear(point).ok() == Some(*value)));
+    let proof = prover_state.narg_string();
+    println!("WHIR proof of {} evaluations of {} bytes", NUM_POINTS, proof.len());
+
+    let mut verifier_state = io.to_verifier_state(proof);
+    verifier_state.public_bytes(&cap_to_bytes(&commitment)).unwrap();
+    verifier_state.ratchet().unwrap();
+    whir.verify_evaluations(&mut verifier_state, &commitment, &points, &values).expect("Invalid proof");
+    println!("WHIR proof successfully verified!");
+}
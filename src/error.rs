@@ -0,0 +1,103 @@
+use std::io;
+
+use spongefish::{DomainSeparatorMismatch, ProofError};
+
+/// Everything that can go wrong in the crate, from bad parameters to rejected proofs.
+#[derive(Debug)]
+pub enum StarkToolsError {
+    /// A degree or degree bound the operation cannot work with.
+    InvalidDegree(String),
+    /// A blowup or folding factor the operation cannot work with.
+    InvalidRate(String),
+    /// Parameters that are inconsistent with each other, such as query counts for the wrong number of rounds.
+    InvalidConfig(String),
+    /// The field has no multiplicative subgroup of `order` elements.
+    MissingRootOfUnity { order: u64 },
+    /// Evaluations that do not cover the subgroup an operation runs over.
+    InvalidDomain(String),
+    /// Building or opening a Merkle tree failed.
+    Merkle(ark_crypto_primitives::Error),
+    /// Reading or writing a stored tree failed.
+    Io(io::Error),
+    /// The transcript does not follow its domain separator, e.g. a proof that ends early.
+    Transcript(DomainSeparatorMismatch),
+    /// Proof bytes that do not decode, or a decoded proof whose shape does not match the configuration.
+    MalformedProof(String),
+    /// A check of the verifier failed in `round`, on `query` or, when `None`, on a message not tied to a query.
+    VerificationFailed { round: usize, query: Option<usize> },
+    /// A check of the verifier failed where no round applies, or where the failing check did not report its position.
+    InvalidProof,
+}
+
+pub type StarkToolsResult<T> = Result<T, StarkToolsError>;
+
+impl std::fmt::Display for StarkToolsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StarkToolsError::InvalidDegree(message) => write!(f, "invalid degree: {message}"),
+            StarkToolsError::InvalidRate(message) => write!(f, "invalid rate: {message}"),
+            StarkToolsError::InvalidConfig(message) => write!(f, "invalid configuration: {message}"),
+            StarkToolsError::MissingRootOfUnity { order } => write!(f, "the field has no root of unity of order {order}"),
+            StarkToolsError::InvalidDomain(message) => write!(f, "invalid evaluation domain: {message}"),
+            StarkToolsError::Merkle(e) => write!(f, "merkle tree: {e}"),
+            StarkToolsError::Io(e) => write!(f, "io: {e}"),
+            StarkToolsError::Transcript(e) => write!(f, "transcript: {e}"),
+            StarkToolsError::MalformedProof(message) => write!(f, "malformed proof: {message}"),
+            StarkToolsError::VerificationFailed { round, query: Some(query) } => write!(f, "verification failed in round {round} on query {query}"),
+            StarkToolsError::VerificationFailed { round, query: None } => write!(f, "verification failed in round {round}"),
+            StarkToolsError::InvalidProof => write!(f, "verification failed"),
+        }
+    }
+}
+
+impl std::error::Error for StarkToolsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StarkToolsError::Merkle(e) => Some(e),
+            StarkToolsError::Io(e) => Some(e),
+            StarkToolsError::Transcript(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ark_crypto_primitives::Error> for StarkToolsError {
+    fn from(e: ark_crypto_primitives::Error) -> Self {
+        StarkToolsError::Merkle(e)
+    }
+}
+
+impl From<io::Error> for StarkToolsError {
+    fn from(e: io::Error) -> Self {
+        StarkToolsError::Io(e)
+    }
+}
+
+impl From<DomainSeparatorMismatch> for StarkToolsError {
+    fn from(e: DomainSeparatorMismatch) -> Self {
+        StarkToolsError::Transcript(e)
+    }
+}
+
+/// `ProofError::InvalidProof` carries no position, so it becomes `InvalidProof`;
+/// verifiers that know the round and query report them themselves.
+impl From<ProofError> for StarkToolsError {
+    fn from(e: ProofError) -> Self {
+        match e {
+            ProofError::InvalidDomainSeparator(e) => StarkToolsError::Transcript(e),
+            ProofError::SerializationError => StarkToolsError::MalformedProof("transcript bytes do not decode".to_string()),
+            ProofError::InvalidProof => StarkToolsError::InvalidProof,
+        }
+    }
+}
+
+/// For protocols whose interfaces still speak spongefish errors.
+impl From<StarkToolsError> for ProofError {
+    fn from(e: StarkToolsError) -> Self {
+        match e {
+            StarkToolsError::Transcript(e) => ProofError::InvalidDomainSeparator(e),
+            StarkToolsError::MalformedProof(_) => ProofError::SerializationError,
+            _ => ProofError::InvalidProof,
+        }
+    }
+}
@@ -1,7 +1,11 @@
 pub mod point;
 pub mod polynomial;
 pub mod merkletree;
+pub mod append_only_merkletree;
 pub mod commitable;
 pub mod spongefish_schnorr;
+pub mod sigma;
+pub mod error;
+pub mod proximityproofs;
 // pub mod test;
 pub mod fields;
\ No newline at end of file
@@ -1,9 +1,16 @@
 use ark_ff::{BigInt, PrimeField};
 use stark_tools::{fields::{Field192, Goldilocks}, merkletree::PedersenTreeConfig, polynomial::{Polynomial, PolynomialCoefficient}};
 
-use crate::proximityproofs::fri::fri_test;
+mod demos;
+
+use demos::merkle::{append_only_test, persistence_test};
+use demos::fri::{fri_batch_test, fri_deep_test, fri_pcs_test, fri_test, fri_transcript_test};
+use demos::stir::stir_test;
+use demos::whir::whir_test;
+use demos::ligero::ligero_test;
+use demos::schnorr::spongefish_test;
+use demos::sigma::sigma_test;
 
-mod proximityproofs;
 // use stark_tools::{fields::Goldilocks, polynomial::{Polynomial, PolynomialCoefficient}};
 
 
@@ -12,13 +19,16 @@ fn main2() {
     println!("{:#?}", x);
     let mut rng = ark_std::test_rng();
 
-    let p: PolynomialCoefficient<Goldilocks> = PolynomialCoefficient::random_poly_smooth_subgroup(&mut rng, 7);
+    let p: PolynomialCoefficient<Goldilocks> = PolynomialCoefficient::random_poly_smooth_subgroup(&mut rng, 7).unwrap();
 
     println!("{:#?} {}", p.coefficients[0], Goldilocks::MODULUS);
 }
 
 
 fn main() {
+    // prover telemetry stays silent unless asked for, e.g. `RUST_LOG=debug` with the `cli` feature
+    #[cfg(feature = "cli")]
+    tracing_subscriber::fmt().with_env_filter(tracing_subscriber::EnvFilter::from_default_env()).init();
     let mut rng = ark_std::test_rng();
 
     // let root64 = F::get_root_of_unity(2).unwrap();
@@ -51,9 +61,19 @@ fn main() {
 
     
 
-    // spongefish_test();
+    spongefish_test();
     // main_test();
     // println!("############################");
-    let pedersen_config = PedersenTreeConfig::new(&mut rng);
+    let pedersen_config = PedersenTreeConfig::new(&mut rng).with_cap_height(2);
+    append_only_test(&pedersen_config);
+    persistence_test(&pedersen_config);
     fri_test(&pedersen_config);
+    fri_batch_test(&pedersen_config);
+    fri_deep_test(&pedersen_config);
+    fri_pcs_test(&pedersen_config);
+    fri_transcript_test(&pedersen_config);
+    stir_test(&pedersen_config);
+    whir_test(&pedersen_config);
+    ligero_test(&pedersen_config);
+    sigma_test();
 }
@@ -10,9 +10,17 @@ use ark_ed_on_bls12_381::{EdwardsProjective, JubjubConfig};
 use ark_crypto_primitives::crh::{
     CRHScheme, TwoToOneCRHScheme,
 };
-use ark_crypto_primitives::merkle_tree::{ByteDigestConverter, Config, MerkleTree, Path};
+use ark_crypto_primitives::merkle_tree::{ByteDigestConverter, Config, DigestConverter, Path};
 use ark_std::rand::Rng;
 use ark_ff::{BigInteger, PrimeField};
+use memmap2::Mmap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path as FsPath;
+use std::sync::Arc;
+
+use crate::error::{StarkToolsError, StarkToolsResult};
 
 
 pub type TwoToOneHash = 
@@ -55,12 +63,30 @@ impl Config for MerkleConfig {
 
 // type LeafVar<ConstraintF> = [UInt8<ConstraintF>];
 
+/// How a tree is filled up to the next power of two when the data does not.
+/// Either way, the tree remembers which leaves hold data and refuses to open the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MerklePadding {
+    /// Padding leaves hold the zero field element.
+    /// They are indistinguishable from real zeros, so verifiers must bound the index themselves.
+    Zero,
+    /// Padding leaves hold a digest computed with the two-to-one parameters.
+    /// Finding a leaf that hashes to it breaks the Pedersen hash, so padding can never be opened as data.
+    EmptyDigest,
+}
+
+/// Hashed with the two-to-one parameters to derive the `MerklePadding::EmptyDigest` leaf.
+const EMPTY_LEAF_TAG: &[u8] = b"stark-tools/empty-leaf";
+
 /// A Merkle tree containing account information.
 /// 
 #[derive(Clone)]
 pub struct PedersenTreeConfig {
     pub leaf_crh_params: Parameters<Projective<JubjubConfig>>,
-    pub two_to_one_crh_params: Parameters<Projective<JubjubConfig>>
+    pub two_to_one_crh_params: Parameters<Projective<JubjubConfig>>,
+    /// Number of top layers published as the commitment: the cap holds `2^cap_height` digests.
+    pub cap_height: usize,
+    pub padding: MerklePadding
 }
 
 
@@ -71,32 +97,383 @@ impl PedersenTreeConfig {
         PedersenTreeConfig {
             leaf_crh_params: leaf_crh_params,
             two_to_one_crh_params: two_to_one_crh_params,
+            cap_height: 0,
+            padding: MerklePadding::EmptyDigest,
+        }
+    }
+
+    pub fn with_cap_height(mut self, cap_height: usize) -> Self {
+        self.cap_height = cap_height;
+        self
+    }
+
+    pub fn with_padding(mut self, padding: MerklePadding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// The cap height actually used for a tree with `num_leaves` leaves.
+    /// Every subtree under the cap needs at least two leaves, so small trees use a lower cap.
+    pub fn cap_height_for(&self, num_leaves: usize) -> usize {
+        let height = num_leaves.trailing_zeros() as usize;
+        self.cap_height.min(height.saturating_sub(1))
+    }
+
+    pub fn hash_leaf<F: PrimeField>(&self, leaf: F) -> StarkToolsResult<LeafDigest> {
+        let leaf_bytes = leaf.into_bigint().to_bytes_be();
+        Ok(<LeafHash as CRHScheme>::evaluate(&self.leaf_crh_params, leaf_bytes)?)
+    }
+
+    /// The digest stored in every padding leaf.
+    pub fn padding_digest<F: PrimeField>(&self) -> StarkToolsResult<LeafDigest> {
+        match self.padding {
+            MerklePadding::Zero => self.hash_leaf(F::ZERO),
+            MerklePadding::EmptyDigest => Ok(<TwoToOneHash as TwoToOneCRHScheme>::evaluate(
+                &self.two_to_one_crh_params,
+                EMPTY_LEAF_TAG,
+                EMPTY_LEAF_TAG
+            )?),
         }
     }
-    pub fn verify_path<F: PrimeField>(&self, path: MerklePath, root: Root, leaf: F) -> Result<bool, ark_crypto_primitives::Error> {
+
+    /// Verifies `path` against the cap node that sits above it.
+    /// A single root is a cap of height 0.
+    pub fn verify_path<F: PrimeField>(&self, path: MerklePath, cap: &[Root], leaf: F) -> StarkToolsResult<bool> {
+        let cap_index = path.leaf_index >> (path.auth_path.len() + 1);
+        let Some(root) = cap.get(cap_index) else {
+            return Ok(false);
+        };
         let leaf_bytes = leaf.into_bigint().to_bytes_be();
-        path.verify(&self.leaf_crh_params, &self.two_to_one_crh_params, &root, leaf_bytes)
+        Ok(path.verify(&self.leaf_crh_params, &self.two_to_one_crh_params, root, leaf_bytes)?)
+    }
+
+    /// Like `verify_path`, but also rejects any position at or past `num_leaves`, i.e. a padding leaf.
+    pub fn verify_bounded_path<F: PrimeField>(&self, path: MerklePath, cap: &[Root], leaf: F, num_leaves: usize) -> StarkToolsResult<bool> {
+        if path.leaf_index >= num_leaves {
+            return Ok(false);
+        }
+        self.verify_path(path, cap, leaf)
+    }
+
+    /// Verifies the aligned block of leaves `block_index * leaves.len()..` of a tree with `num_leaves`
+    /// leaves against `auth_path`, as produced by `PedersenMerkleTree::generate_block_proof`.
+    /// `leaves.len()` must be a power of two, at least two.
+    pub fn verify_block<F: PrimeField>(&self, leaves: &[F], block_index: usize, auth_path: &[Root], cap: &[Root], num_leaves: usize) -> StarkToolsResult<bool> {
+        if leaves.len() < 2 || !leaves.len().is_power_of_two() || !num_leaves.is_power_of_two() {
+            return Ok(false);
+        }
+        let block_layer = leaves.len().trailing_zeros() as usize;
+        let cap_layer = num_leaves.trailing_zeros() as usize - self.cap_height_for(num_leaves);
+
+        let leaf_digests = leaves.iter().map(|leaf| self.hash_leaf(*leaf)).collect::<Result<Vec<LeafDigest>, _>>()?;
+        let mut layer: Vec<Root> = leaf_digests
+            .chunks(2)
+            .map(|pair| <TwoToOneHash as TwoToOneCRHScheme>::evaluate(
+                &self.two_to_one_crh_params,
+                ByteDigestConverter::<LeafDigest>::convert(pair[0])?,
+                ByteDigestConverter::<LeafDigest>::convert(pair[1])?,
+            ))
+            .collect::<Result<_, _>>()?;
+        for _ in 1..block_layer.min(cap_layer) {
+            layer = layer
+                .chunks(2)
+                .map(|pair| <TwoToOneHash as TwoToOneCRHScheme>::compress(&self.two_to_one_crh_params, &pair[0], &pair[1]))
+                .collect::<Result<_, _>>()?;
+        }
+
+        // a block reaching past the cap layer is checked against the cap nodes it covers
+        if block_layer >= cap_layer {
+            let first = (block_index << block_layer) >> cap_layer;
+            return Ok(auth_path.is_empty() && cap.get(first..first + layer.len()) == Some(&layer[..]));
+        }
+        if auth_path.len() != cap_layer - block_layer {
+            return Ok(false);
+        }
+        let mut node = layer[0];
+        let mut index = block_index;
+        for sibling in auth_path.iter().rev() {
+            node = if index & 1 == 0 {
+                <TwoToOneHash as TwoToOneCRHScheme>::compress(&self.two_to_one_crh_params, &node, sibling)?
+            } else {
+                <TwoToOneHash as TwoToOneCRHScheme>::compress(&self.two_to_one_crh_params, sibling, &node)?
+            };
+            index >>= 1;
+        }
+        Ok(cap.get(index) == Some(&node))
     }
 }
 
 
+pub type LeafDigest = <LeafHash as CRHScheme>::Output;
 /// The root of the account Merkle tree.
 pub type Root = <TwoToOneHash as TwoToOneCRHScheme>::Output;
 /// A membership proof for a given account.
 pub type MerklePath = Path<MerkleConfig>;
-pub type PedersenMerkleTree = MerkleTree<MerkleConfig>;
-
-
-pub fn new_pedersen_merkletree<F: PrimeField>(pedersen_config: &PedersenTreeConfig, leaves: Vec<F>) -> PedersenMerkleTree {
-    let mut leaves_bytes: Vec<Vec<u8>> = vec![];
-    leaves
-        .iter()
-        .map(|l| l.into_bigint().to_bytes_be()).for_each(|l| leaves_bytes.push(l));
-    
-    MerkleTree::new(
-        &pedersen_config.leaf_crh_params,
-        &pedersen_config.two_to_one_crh_params,
-        leaves_bytes, // the i-th entry is the i-th leaf.
-    )
-    .unwrap()
+/// The top `cap_height` layers of a tree, left to right.
+pub type MerkleCap = Vec<Root>;
+
+/// Where the nodes of a `PedersenMerkleTree` live.
+#[derive(Clone)]
+enum NodeStore {
+    Memory(Vec<Root>),
+    /// Digests serialised back to back in a memory-mapped file, starting at `offset`.
+    Mapped { map: Arc<Mmap>, offset: usize },
+}
+
+impl NodeStore {
+    fn get(&self, position: usize) -> Root {
+        match self {
+            NodeStore::Memory(nodes) => nodes[position],
+            NodeStore::Mapped { map, offset } => {
+                let start = offset + position * DIGEST_BYTES;
+                Root::from_le_bytes_mod_order(&map[start..start + DIGEST_BYTES])
+            }
+        }
+    }
+}
+
+/// Serialised size of a digest in a tree file.
+const DIGEST_BYTES: usize = 32;
+const TREE_FILE_MAGIC: &[u8; 4] = b"STMT";
+const TREE_FILE_VERSION: u32 = 1;
+
+/// A Merkle tree whose top `cap_height` layers are published as the cap.
+/// Paths stop below the cap, so each one is `cap_height` hashes shorter than a full path.
+/// The leaves are padded up to a power of two following `PedersenTreeConfig::padding`.
+///
+/// Only the layers from the leaves up to the cap are kept, either in memory or in a
+/// memory-mapped file written by `save` and reopened by `open`.
+#[derive(Clone)]
+pub struct PedersenMerkleTree {
+    pub cap_height: usize,
+    /// Number of leaves the data spans; positions from here up to `capacity()` are padding.
+    num_leaves: usize,
+    /// Positions holding data in a sparse tree, `None` when all of `0..num_leaves` do.
+    occupied: Option<BTreeSet<usize>>,
+    capacity: usize,
+    /// Layers in order from the leaf digests (layer 0) up to the cap.
+    nodes: NodeStore,
+    two_to_one_crh_params: Parameters<Projective<JubjubConfig>>
+}
+
+impl PedersenMerkleTree {
+    /// The layer holding the cap; paths contain one node from each layer below it.
+    fn cap_layer(&self) -> usize {
+        self.capacity.trailing_zeros() as usize - self.cap_height
+    }
+
+    fn node(&self, layer: usize, index: usize) -> Root {
+        // layer `l` holds `capacity >> l` nodes
+        let layer_start = (0..layer).map(|l| self.capacity >> l).sum::<usize>();
+        self.nodes.get(layer_start + index)
+    }
+
+    pub fn cap(&self) -> MerkleCap {
+        let cap_layer = self.cap_layer();
+        (0..1 << self.cap_height).map(|i| self.node(cap_layer, i)).collect()
+    }
+
+    /// The root of the full tree, obtained by hashing the cap up to a single node.
+    pub fn root(&self) -> StarkToolsResult<Root> {
+        let mut layer = self.cap();
+        while layer.len() > 1 {
+            layer = layer
+                .chunks(2)
+                .map(|pair| <TwoToOneHash as TwoToOneCRHScheme>::compress(&self.two_to_one_crh_params, &pair[0], &pair[1]))
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(layer[0])
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.num_leaves
+    }
+
+    /// Number of leaves including padding, always a power of two.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Whether leaf `index` holds data rather than padding.
+    pub fn is_occupied(&self, index: usize) -> bool {
+        index < self.num_leaves && self.occupied.as_ref().is_none_or(|o| o.contains(&index))
+    }
+
+    /// Returns the path from leaf `index` up to its cap node.
+    /// The path keeps the global `index`, which is what `PedersenTreeConfig::verify_path` expects.
+    /// Padding leaves cannot be opened.
+    pub fn generate_proof(&self, index: usize) -> StarkToolsResult<MerklePath> {
+        if !self.is_occupied(index) {
+            return Err(merkle_error(format!("leaf index {index} holds no data")));
+        }
+        // ordered from the layer below the cap down to the layer above the leaves
+        let auth_path = (1..self.cap_layer())
+            .rev()
+            .map(|layer| self.node(layer, (index >> layer) ^ 1))
+            .collect();
+        Ok(MerklePath {
+            leaf_sibling_hash: self.node(0, index ^ 1),
+            auth_path,
+            leaf_index: index,
+        })
+    }
+
+    /// Returns the path from the aligned block of `block_size` leaves starting at
+    /// `block_index * block_size` up to its cap node, ordered like `MerklePath::auth_path`.
+    /// The path is empty when the block spans whole cap nodes.
+    pub fn generate_block_proof(&self, block_index: usize, block_size: usize) -> StarkToolsResult<Vec<Root>> {
+        if block_size < 2 || !block_size.is_power_of_two() {
+            return Err(merkle_error(format!("block size {block_size} is not a power of two above one")));
+        }
+        let block_layer = block_size.trailing_zeros() as usize;
+        if let Some(index) = (block_index * block_size..(block_index + 1) * block_size).find(|&i| !self.is_occupied(i)) {
+            return Err(merkle_error(format!("leaf index {index} holds no data")));
+        }
+        Ok((block_layer..self.cap_layer())
+            .rev()
+            .map(|layer| self.node(layer, (block_index >> (layer - block_layer)) ^ 1))
+            .collect())
+    }
+
+    /// Writes the tree to `path` so it can be reopened with `open` without rehashing.
+    pub fn save<P: AsRef<FsPath>>(&self, path: P) -> StarkToolsResult<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(TREE_FILE_MAGIC)?;
+        file.write_all(&TREE_FILE_VERSION.to_le_bytes())?;
+        for value in [self.num_leaves, self.capacity, self.cap_height] {
+            file.write_all(&(value as u64).to_le_bytes())?;
+        }
+        match &self.occupied {
+            None => file.write_all(&u64::MAX.to_le_bytes())?,
+            Some(occupied) => {
+                file.write_all(&(occupied.len() as u64).to_le_bytes())?;
+                for index in occupied {
+                    file.write_all(&(*index as u64).to_le_bytes())?;
+                }
+            }
+        }
+        let num_nodes = (0..=self.cap_layer()).map(|l| self.capacity >> l).sum::<usize>();
+        for position in 0..num_nodes {
+            let mut digest_bytes = self.nodes.get(position).into_bigint().to_bytes_le();
+            digest_bytes.resize(DIGEST_BYTES, 0);
+            file.write_all(&digest_bytes)?;
+        }
+        Ok(file.flush()?)
+    }
+
+    /// Maps a tree written by `save`. Nodes are read from the file on demand, so
+    /// opening is cheap even for very large trees.
+    /// `pedersen_config` must be the configuration the tree was built with.
+    pub fn open<P: AsRef<FsPath>>(path: P, pedersen_config: &PedersenTreeConfig) -> StarkToolsResult<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the file is only read, callers must not modify it while the tree is in use.
+        let map = unsafe { Mmap::map(&file)? };
+        let invalid = |message: &str| StarkToolsError::Io(io::Error::new(io::ErrorKind::InvalidData, message.to_string()));
+
+        let mut reader = &map[..];
+        let mut read_u64 = || -> io::Result<u64> {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let header = read_u64()?.to_le_bytes();
+        if &header[..4] != TREE_FILE_MAGIC || header[4..] != TREE_FILE_VERSION.to_le_bytes() {
+            return Err(invalid("not a merkle tree file"));
+        }
+        let num_leaves = read_u64()? as usize;
+        let capacity = read_u64()? as usize;
+        let cap_height = read_u64()? as usize;
+        let occupied = match read_u64()? {
+            u64::MAX => None,
+            count => Some((0..count).map(|_| read_u64().map(|i| i as usize)).collect::<io::Result<BTreeSet<usize>>>()?),
+        };
+        if !capacity.is_power_of_two() || capacity < 2 || num_leaves > capacity || cap_height >= capacity.trailing_zeros() as usize {
+            return Err(invalid("inconsistent merkle tree header"));
+        }
+
+        let offset = map.len() - reader.len();
+        // the header is untrusted, so a huge capacity must not overflow the expected length
+        let node_bytes = (0..=capacity.trailing_zeros() as usize - cap_height)
+            .try_fold(0usize, |sum, l| sum.checked_add(capacity >> l))
+            .and_then(|num_nodes| num_nodes.checked_mul(DIGEST_BYTES))
+            .ok_or_else(|| invalid("merkle tree capacity out of range"))?;
+        if reader.len() != node_bytes {
+            return Err(invalid("truncated merkle tree file"));
+        }
+        Ok(PedersenMerkleTree {
+            cap_height,
+            num_leaves,
+            occupied,
+            capacity,
+            nodes: NodeStore::Mapped { map: Arc::new(map), offset },
+            two_to_one_crh_params: pedersen_config.two_to_one_crh_params.clone()
+        })
+    }
+}
+
+pub(crate) fn merkle_error(message: String) -> StarkToolsError {
+    let e: Box<dyn std::error::Error + Send + Sync> = message.into();
+    StarkToolsError::Merkle(ark_crypto_primitives::Error::GenericError(e))
+}
+
+/// Commits to `leaves` in order. Any length is accepted; the tree is padded to the next power of two.
+pub fn new_pedersen_merkletree<F: PrimeField>(pedersen_config: &PedersenTreeConfig, leaves: Vec<F>) -> StarkToolsResult<PedersenMerkleTree> {
+    let num_leaves = leaves.len();
+    let leaves = leaves.into_iter().enumerate().collect();
+    build_pedersen_merkletree(pedersen_config, leaves, num_leaves, None)
+}
+
+/// Commits to a vector of length `num_leaves` where only the positions in `leaves` hold data.
+/// Every other position is a padding leaf and cannot be opened.
+pub fn new_sparse_pedersen_merkletree<F: PrimeField>(pedersen_config: &PedersenTreeConfig, leaves: BTreeMap<usize, F>, num_leaves: usize) -> StarkToolsResult<PedersenMerkleTree> {
+    if let Some((&index, _)) = leaves.range(num_leaves..).next() {
+        return Err(merkle_error(format!("leaf index {index} out of range for {num_leaves} leaves")));
+    }
+    let occupied = leaves.keys().cloned().collect();
+    build_pedersen_merkletree(pedersen_config, leaves.into_iter().collect(), num_leaves, Some(occupied))
+}
+
+fn build_pedersen_merkletree<F: PrimeField>(
+    pedersen_config: &PedersenTreeConfig,
+    leaves: Vec<(usize, F)>,
+    num_leaves: usize,
+    occupied: Option<BTreeSet<usize>>
+) -> StarkToolsResult<PedersenMerkleTree> {
+    // the tree needs a power of two and at least two leaves
+    let capacity = num_leaves.next_power_of_two().max(2);
+    let padding = pedersen_config.padding_digest::<F>()?;
+    let mut leaf_digests: Vec<LeafDigest> = vec![padding; capacity];
+    for (index, leaf) in leaves {
+        leaf_digests[index] = pedersen_config.hash_leaf(leaf)?;
+    }
+
+    let cap_height = pedersen_config.cap_height_for(capacity);
+    let cap_layer = capacity.trailing_zeros() as usize - cap_height;
+
+    // the layer above the leaves hashes converted leaf digests, exactly as `Path::verify` does
+    let mut nodes: Vec<Root> = leaf_digests.clone();
+    let mut layer: Vec<Root> = leaf_digests
+        .chunks(2)
+        .map(|pair| <TwoToOneHash as TwoToOneCRHScheme>::evaluate(
+            &pedersen_config.two_to_one_crh_params,
+            ByteDigestConverter::<LeafDigest>::convert(pair[0])?,
+            ByteDigestConverter::<LeafDigest>::convert(pair[1])?,
+        ))
+        .collect::<Result<_, _>>()?;
+    nodes.extend_from_slice(&layer);
+    for _ in 1..cap_layer {
+        layer = layer
+            .chunks(2)
+            .map(|pair| <TwoToOneHash as TwoToOneCRHScheme>::compress(&pedersen_config.two_to_one_crh_params, &pair[0], &pair[1]))
+            .collect::<Result<_, _>>()?;
+        nodes.extend_from_slice(&layer);
+    }
+    Ok(PedersenMerkleTree {
+        cap_height,
+        num_leaves,
+        occupied,
+        capacity,
+        nodes: NodeStore::Memory(nodes),
+        two_to_one_crh_params: pedersen_config.two_to_one_crh_params.clone()
+    })
 }
@@ -2,10 +2,11 @@ use std::{collections::HashMap};
 use std::fmt::Debug;
 use std::{vec};
 
-use ark_ff::{BigInt, BigInteger, PrimeField};
+use ark_ff::{batch_inversion, BigInt, BigInteger, PrimeField};
 use ark_std::rand::Rng;
 use bimap::{BiHashMap};
 
+use crate::error::{StarkToolsError, StarkToolsResult};
 use crate::point::Point;
 
 // type F<T, const N:usize> = Fp<MontBackend<T, N>, N>;
@@ -76,15 +77,32 @@ impl<'a, F: PrimeField> std::fmt::Display for PolynomialPoints<F> {
     }
 }
 
+impl<F: PrimeField> PolynomialPoints<F> {
+    /// Evaluates the polynomial at `z` straight from the points, which must cover a whole multiplicative
+    /// subgroup of size `n` as in `ifft`, with the barycentric formula `f(z) = (z^n - 1)/n * sum_x f(x) * x / (z - x)`.
+    pub fn evaluate(&self, z: F) -> StarkToolsResult<F> {
+        if let Some(point) = self.points.get(&z) {
+            return Ok(point.get_y());
+        }
+        let n = self.points.len() as u64;
+        let n_inv = F::from(n).inverse()
+            .ok_or_else(|| StarkToolsError::InvalidDomain(format!("{n} points is a multiple of the characteristic")))?;
+        let mut denominators: Vec<F> = self.points.keys().map(|&x| z - x).collect();
+        batch_inversion(&mut denominators);
+        let sum: F = self.points.values().zip(&denominators).map(|(point, d)| point.get_y() * point.get_x() * d).sum();
+        Ok((z.pow([n]) - F::ONE) * n_inv * sum)
+    }
+}
+
 pub trait Polynomial<F: PrimeField, Raw> 
 where Self: Clone {
     fn zero(degree: u64) -> Self;
-    fn new(degree: u64, raw: Raw) -> Self;
+    fn new(degree: u64, raw: Raw) -> StarkToolsResult<Self>;
     fn random_poly<R: Rng + ?Sized>(rng: &mut R, degree: u64) -> Self;
-    fn random_poly_smooth_subgroup<R: Rng + ?Sized>(rng: &mut R, degree: u64) -> Self;
+    fn random_poly_smooth_subgroup<R: Rng + ?Sized>(rng: &mut R, degree: u64) -> StarkToolsResult<Self>;
     fn constant(rate: u64) -> Self;
-    fn fft(self, rate: u64) -> PolynomialPoints<F>;
-    fn ifft(self, rate: u64) -> PolynomialCoefficient<F>;
+    fn fft(self, rate: u64) -> StarkToolsResult<PolynomialPoints<F>>;
+    fn ifft(self, rate: u64) -> StarkToolsResult<PolynomialCoefficient<F>>;
 }
 
 impl <'a, F: PrimeField> Polynomial<F, Vec<Point<F>>> for PolynomialPoints<F> 
@@ -97,12 +115,12 @@ impl <'a, F: PrimeField> Polynomial<F, Vec<Point<F>>> for PolynomialPoints<F>
         }
     }
     
-    fn new(degree: u64, raw: Vec<Point<F>>) -> Self {
-        Self {
+    fn new(degree: u64, raw: Vec<Point<F>>) -> StarkToolsResult<Self> {
+        Ok(Self {
             degree,
             points: raw.iter().map(|p| (p.get_x(), Box::new(p.clone()))).collect(),
             roots_preimage: None
-        }
+        })
     }
 
     fn constant(rate: u64) -> Self {
@@ -128,10 +146,10 @@ impl <'a, F: PrimeField> Polynomial<F, Vec<Point<F>>> for PolynomialPoints<F>
         }
     }
 
-    fn random_poly_smooth_subgroup<R: Rng + ?Sized>(rng: &mut R, degree: u64) -> Self {
+    fn random_poly_smooth_subgroup<R: Rng + ?Sized>(rng: &mut R, degree: u64) -> StarkToolsResult<Self> {
         let mut points = HashMap::new();
         let mut roots_preimage = BiHashMap::new();
-        let omega = F::get_root_of_unity((degree + 1)).unwrap();
+        let omega: F = root_of_unity(degree + 1)?;
         let mut root = F::ONE;
         for i in 0..(degree + 1) {
             roots_preimage.insert(root, i);
@@ -139,16 +157,55 @@ impl <'a, F: PrimeField> Polynomial<F, Vec<Point<F>>> for PolynomialPoints<F>
             points.insert(point.get_x(), Box::new(point));
             root = root * omega;
         }
-        PolynomialPoints { degree, points, roots_preimage: Some(roots_preimage) }
+        Ok(PolynomialPoints { degree, points, roots_preimage: Some(roots_preimage) })
     }
 
-    fn fft(self, rate: u64) -> PolynomialPoints<F> {
+    fn fft(self, rate: u64) -> StarkToolsResult<PolynomialPoints<F>> {
         // TODO add error handling if the extended degree is less than degree
-        self
+        Ok(self)
     }
 
-    fn ifft(self, rate: u64) -> PolynomialCoefficient<F> {
-        todo!()
+    /// Interpolates the `degree + 1` coefficients from the points, which must cover a whole
+    /// multiplicative subgroup (the domain `fft` produced); `rate` is implied by the number of points.
+    fn ifft(self, _rate: u64) -> StarkToolsResult<PolynomialCoefficient<F>> {
+        if self.points.len() as u64 <= self.degree {
+            return Err(StarkToolsError::InvalidDomain(format!("{} points cannot determine a polynomial of degree {}", self.points.len(), self.degree)));
+        }
+        let n_inv = F::from(self.points.len() as u64).inverse()
+            .ok_or_else(|| StarkToolsError::InvalidDomain(format!("{} points is a multiple of the characteristic", self.points.len())))?;
+        let mut coefficients = vec![F::ZERO; self.degree as usize + 1];
+        // c_k = 1/n * sum_x f(x) * x^-k
+        for point in self.points.values() {
+            let x_inv = point.get_x().inverse().ok_or_else(|| StarkToolsError::InvalidDomain("zero is not a root of unity".to_string()))?;
+            let mut x_inv_k = n_inv;
+            for coefficient in coefficients.iter_mut() {
+                *coefficient += point.get_y() * x_inv_k;
+                x_inv_k *= x_inv;
+            }
+        }
+        Ok(PolynomialCoefficient { degree: self.degree, coefficients })
+    }
+}
+
+impl<F: PrimeField> PolynomialCoefficient<F> {
+    /// Evaluates the polynomial at `x` with Horner's rule.
+    pub fn evaluate(&self, x: F) -> F {
+        self.coefficients.iter().rev().fold(F::ZERO, |acc, c| acc * x + c)
+    }
+
+    /// Evaluates the multilinear polynomial with the same coefficients at `point`: coefficient `i` belongs to
+    /// the monomial of the variables whose bits are set in `i`, so `evaluate(x)` is `evaluate_multilinear(&[x, x^2, x^4, ...])`.
+    /// Fails if there are more than `2^point.len()` coefficients.
+    pub fn evaluate_multilinear(&self, point: &[F]) -> StarkToolsResult<F> {
+        if point.len() >= usize::BITS as usize || self.coefficients.len() > 1 << point.len() {
+            return Err(StarkToolsError::InvalidDegree(format!("{} coefficients do not fit {} variables", self.coefficients.len(), point.len())));
+        }
+        let mut values = self.coefficients.clone();
+        values.resize(1 << point.len(), F::ZERO);
+        for x in point {
+            values = values.chunks(2).map(|pair| pair[0] + *x * pair[1]).collect();
+        }
+        Ok(values[0])
     }
 }
 
@@ -161,13 +218,15 @@ impl <F: PrimeField> Polynomial<F, Vec<F>> for PolynomialCoefficient<F>
         }
     }
 
-    fn new(degree: u64, raw: Vec<F>) -> Self {
-        assert!(raw.len() >= (degree + 1) as usize);
+    fn new(degree: u64, raw: Vec<F>) -> StarkToolsResult<Self> {
+        if (raw.len() as u64) <= degree {
+            return Err(StarkToolsError::InvalidDegree(format!("{} coefficients cannot make a polynomial of degree {degree}", raw.len())));
+        }
 
-        Self {
+        Ok(Self {
             degree: degree,
             coefficients: raw
-        }
+        })
     }
 
 
@@ -186,16 +245,25 @@ impl <F: PrimeField> Polynomial<F, Vec<F>> for PolynomialCoefficient<F>
             coefficients: coefficients
         }
     }
-    fn random_poly_smooth_subgroup<R: Rng + ?Sized>(rng: &mut R, degree: u64) -> Self {
-        Self::random_poly(rng, degree)
+    fn random_poly_smooth_subgroup<R: Rng + ?Sized>(rng: &mut R, degree: u64) -> StarkToolsResult<Self> {
+        Ok(Self::random_poly(rng, degree))
     }
 
-    fn fft(self, rate: u64) -> PolynomialPoints<F> {
-        let extended_degree = (self.degree + 1) * rate;
-        let omega: F = F::get_root_of_unity(extended_degree as u64).unwrap();
+    /// Evaluates over the subgroup of `(degree + 1) * rate` roots of unity; `degree + 1` must be a power of two.
+    /// Coefficients past the end of `coefficients` are zero.
+    fn fft(self, rate: u64) -> StarkToolsResult<PolynomialPoints<F>> {
+        if !(self.degree + 1).is_power_of_two() {
+            return Err(StarkToolsError::InvalidDegree(format!("degree {} + 1 is not a power of two", self.degree)));
+        }
+        if rate == 0 {
+            return Err(StarkToolsError::InvalidRate("the rate is zero".to_string()));
+        }
+        let extended_degree = (self.degree + 1).checked_mul(rate)
+            .ok_or_else(|| StarkToolsError::InvalidRate(format!("a domain of {} * {rate} points does not fit in a u64", self.degree + 1)))?;
+        let omega: F = root_of_unity(extended_degree)?;
         let mut root = F::ONE;
         if self.degree == 0 {
-            let value = self.coefficients.get(0).unwrap();
+            let value = self.coefficients.first().copied().unwrap_or(F::ZERO);
             let mut points = HashMap::new();
             let mut roots_preimage = BiHashMap::new();
             for i in 0..extended_degree {
@@ -203,24 +271,24 @@ impl <F: PrimeField> Polynomial<F, Vec<F>> for PolynomialCoefficient<F>
                 points.insert(root, Box::new(Point::new(
                     root,
                     // Because it's a degree zero polynomial it's value is always fixed
-                    *value
+                    value
                 )));
                 root = root * omega;
             }
-            return PolynomialPoints { 
+            return Ok(PolynomialPoints { 
                 degree: 1, 
                 points: points,
                 roots_preimage: Some(roots_preimage)
-            }
+            })
         }
         let p_e: PolynomialPoints<F> = PolynomialCoefficient { 
             degree: self.degree/2, 
             coefficients: self.coefficients.iter().step_by(2).cloned().collect()
-        }.fft(rate);
+        }.fft(rate)?;
         let p_o: PolynomialPoints<F> = PolynomialCoefficient { 
             degree: self.degree/2, 
             coefficients: self.coefficients.iter().skip(1).step_by(2).cloned().collect()
-        }.fft(rate);
+        }.fft(rate)?;
 
         let mut points: HashMap<F, Box<Point<F>>> = HashMap::new();
         let mut roots_preimage = BiHashMap::new();
@@ -253,18 +321,18 @@ impl <F: PrimeField> Polynomial<F, Vec<F>> for PolynomialCoefficient<F>
                 );
             }
         }
-        PolynomialPoints {
+        Ok(PolynomialPoints {
             degree: self.degree,
             points: points,
             roots_preimage: Some(roots_preimage)
-        }
+        })
     }
 
     fn constant(rate: u64) -> Self {
         todo!()
     }
 
-    fn ifft(self, extended_degree: u64) -> PolynomialCoefficient<F> {
+    fn ifft(self, extended_degree: u64) -> StarkToolsResult<PolynomialCoefficient<F>> {
         todo!()
     }
 }
@@ -272,37 +340,48 @@ pub trait Foldable2<F: PrimeField, const N: usize>
 where Self: Sized
 
 {
-    fn fold(&self, rate: u64, folding_number: F) -> PolynomialPoints<F>;
-    fn fold_bigint(&self, rate: u64, folding_number: BigInt<N>) -> PolynomialPoints<F> {
+    fn fold(&self, rate: u64, folding_number: F) -> StarkToolsResult<PolynomialPoints<F>>;
+    fn fold_bigint(&self, rate: u64, folding_number: BigInt<N>) -> StarkToolsResult<PolynomialPoints<F>> {
         self.fold(rate, F::from_be_bytes_mod_order(&folding_number.to_bytes_be()))
     }
 }
 
 impl <const N: usize, F: PrimeField> Foldable2<F, N> for PolynomialPoints<F> 
 {
-    fn fold(&self, rate: u64, folding_number: F) -> PolynomialPoints<F> {
+    /// Fails if the points do not cover the subgroup of `(degree + 1) * rate` roots of unity,
+    /// or if `folding_number` is one of them.
+    fn fold(&self, rate: u64, folding_number: F) -> StarkToolsResult<PolynomialPoints<F>> {
         let extended_degree = (self.degree+1) * rate;
-        let omega = F::get_root_of_unity(extended_degree).unwrap();
+        let omega: F = root_of_unity(extended_degree)?;
+        let value_at = |x: &F| self.points.get(x).map(|point| point.get_y())
+            .ok_or_else(|| StarkToolsError::InvalidDomain(format!("no evaluation at the root of unity {x}")));
         let mut root = F::ONE;
 
         let mut points: HashMap<F, Box<Point<F>>> = HashMap::new();
         let mut roots_preimage: BiHashMap<F, u64> = BiHashMap::new();
         // let p_1 = self.points.get(&(-root)).unwrap();
         for i in 0..extended_degree/2 {
-            assert_ne!(folding_number, root);
+            if folding_number == root {
+                return Err(StarkToolsError::InvalidDomain(format!("the folding randomness {folding_number} is a point of the domain")));
+            }
             let w2 = root * root;
             roots_preimage.insert(w2, i);
             let value = 
-                (self.points.get(&root).unwrap().get_y() * (root + folding_number)
-                + self.points.get(&(-root)).unwrap().get_y() * (root - folding_number)) / root.double();
+                (value_at(&root)? * (root + folding_number)
+                + value_at(&(-root))? * (root - folding_number)) / root.double();
             points.insert(w2, Box::new(Point::new(w2, value)));
             root = root * omega
         }
 
-        PolynomialPoints {
+        Ok(PolynomialPoints {
             degree: self.degree / 2,
             points,
             roots_preimage: Some(roots_preimage)
-        }
+        })
     }
 }
+
+/// A generator of the subgroup of `order` roots of unity.
+fn root_of_unity<F: PrimeField>(order: u64) -> StarkToolsResult<F> {
+    F::get_root_of_unity(order).ok_or(StarkToolsError::MissingRootOfUnity { order })
+}
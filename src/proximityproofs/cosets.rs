@@ -0,0 +1,259 @@
+use ark_ff::{BigInteger, PrimeField};
+use spongefish::{BytesToUnitDeserialize, BytesToUnitSerialize, DomainSeparator, DuplexSpongeInterface, ProofError, ProverState, VerifierState};
+use crate::{commitable::{Commited, LeafOrdering}, merkletree::{PedersenTreeConfig, Root}, polynomial::PolynomialPoints};
+use crate::proximityproofs::{transcript::{add_schema, TranscriptOp}, utils::{bytes_to_bls, bytes_to_path, digests_to_bytes, leaf_value, path_to_bytes, prove_leaf_index, DIGEST_BYTES}};
+use std::marker::PhantomData;
+
+/// Opens the cosets that fold into one point, in codewords committed with `leaf_ordering`.
+/// With `LeafOrdering::Natural` every leaf of a coset comes with its own path; with `LeafOrdering::BitReversed`
+/// the coset is one block of leaves under a single path.
+pub struct CosetOpener<'a, F> {
+    pub pedersen_config: &'a PedersenTreeConfig,
+    pub leaf_ordering: LeafOrdering,
+    pub folding_factor: u64,
+    marker: PhantomData<F>
+}
+
+impl<'a, F: PrimeField> CosetOpener<'a, F> {
+    pub fn new(pedersen_config: &'a PedersenTreeConfig, leaf_ordering: LeafOrdering, folding_factor: u64) -> Self {
+        Self { pedersen_config, leaf_ordering, folding_factor, marker: PhantomData }
+    }
+
+    /// Number of bytes a field element takes in the transcript.
+    pub fn field_bytes(&self) -> usize {
+        F::BigInt::NUM_LIMBS * 8
+    }
+
+    /// Number of digests in the cap of a tree with `num_leaves` leaves.
+    pub fn cap_size(&self, num_leaves: u64) -> usize {
+        1 << self.pedersen_config.cap_height_for(num_leaves as usize)
+    }
+
+    /// Number of digests in a Merkle path (leaf sibling included) from a leaf up to the cap.
+    pub fn path_length(&self, num_leaves: u64) -> usize {
+        num_leaves.trailing_zeros() as usize - self.pedersen_config.cap_height_for(num_leaves as usize)
+    }
+
+    /// Number of digests in the path above a block of `folding_factor` leaves.
+    pub fn block_path_length(&self, num_leaves: u64) -> usize {
+        self.path_length(num_leaves).saturating_sub(self.folding_factor.trailing_zeros() as usize)
+    }
+
+    /// Domain indices of the coset that folds into index `fold_index` of a domain of `max_index` points.
+    pub fn coset(&self, fold_index: u64, max_index: u64) -> Vec<u64> {
+        let stride = max_index / self.folding_factor;
+        (0..self.folding_factor).map(|j| fold_index + j * stride).collect()
+    }
+
+    pub fn add_coset_opening<H: DuplexSpongeInterface>(&self, ds: DomainSeparator<H>, max_index: u64) -> DomainSeparator<H> {
+        add_schema(ds, &self.coset_opening_schema(max_index))
+    }
+
+    /// The messages `write_coset_opening` sends for a coset of a domain of `max_index` points.
+    pub fn coset_opening_schema(&self, max_index: u64) -> Vec<TranscriptOp> {
+        let s = self.field_bytes();
+        let mut schema = vec![];
+        match self.leaf_ordering {
+            LeafOrdering::Natural => {
+                for _ in 0..self.folding_factor {
+                    schema.push(TranscriptOp::Message { bytes: s, label: "coset leaf value" });
+                    for _ in 0..self.path_length(max_index) {
+                        schema.push(TranscriptOp::Message { bytes: DIGEST_BYTES, label: "coset leaf proof" });
+                    }
+                }
+            }
+            LeafOrdering::BitReversed => {
+                schema.push(TranscriptOp::Message { bytes: s * self.folding_factor as usize, label: "coset values" });
+                // the coset is a block of leaves, only the path above the block is sent
+                for _ in 0..self.block_path_length(max_index) {
+                    schema.push(TranscriptOp::Message { bytes: DIGEST_BYTES, label: "coset block proof" });
+                }
+            }
+        }
+        schema
+    }
+
+    /// Opens the coset folding into `fold_leaf_index`.
+    pub fn write_coset_opening<H: DuplexSpongeInterface>(
+        &self,
+        polynomial: &Commited<PolynomialPoints<F>>,
+        fold_leaf_index: u64,
+        max_index: u64,
+        prover_state: &mut ProverState<H>
+    ) -> Result<(), ProofError> {
+        let coset = self.coset(fold_leaf_index, max_index);
+        match self.leaf_ordering {
+            LeafOrdering::Natural => {
+                for index in coset {
+                    write_merkleproofs(polynomial, index, prover_state)?;
+                }
+                Ok(())
+            }
+            LeafOrdering::BitReversed => write_merkleproofs_block(polynomial, &coset, prover_state),
+        }
+    }
+
+    /// Reads and checks the opening of the coset folding into `fold_leaf_index`, returning its values in coset order.
+    pub fn read_coset_opening<H: DuplexSpongeInterface>(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &[Root],
+        fold_leaf_index: u64,
+        max_index: u64
+    ) -> Result<Vec<F>, ProofError> {
+        let folding_factor = self.folding_factor as usize;
+        let coset = self.coset(fold_leaf_index, max_index);
+        let mut coset_values = Vec::with_capacity(folding_factor);
+        match self.leaf_ordering {
+            LeafOrdering::Natural => {
+                for index in &coset {
+                    let value = read_field_element(self.field_bytes(), verifier_state)?;
+                    read_and_verify_merkle(
+                        value,
+                        self.path_length(max_index),
+                        *index as usize,
+                        commitment,
+                        self.pedersen_config,
+                        verifier_state
+                    )?;
+                    coset_values.push(value);
+                }
+            }
+            LeafOrdering::BitReversed => {
+                for _ in &coset {
+                    coset_values.push(read_field_element(self.field_bytes(), verifier_state)?);
+                }
+                // the block holds the coset in leaf order
+                let mut block = vec![F::ZERO; folding_factor];
+                for (index, value) in coset.iter().zip(&coset_values) {
+                    block[self.leaf_ordering.leaf_position(*index, max_index) as usize % folding_factor] = *value;
+                }
+                read_and_verify_merkle_block(
+                    &block,
+                    self.leaf_ordering.leaf_position(fold_leaf_index, max_index / self.folding_factor) as usize,
+                    self.block_path_length(max_index),
+                    max_index as usize,
+                    commitment,
+                    self.pedersen_config,
+                    verifier_state
+                )?;
+            }
+        }
+        Ok(coset_values)
+    }
+}
+
+/// Folds the values `f(x * zeta^j)` of a coset, where `zeta` generates the roots of unity of order `values.len()`.
+/// Mirrors the prover's successive binary folds, squaring the randomness each time.
+pub fn fold_coset<F: PrimeField>(values: &[F], x: F, folding_randomness: F) -> F {
+    let randomness: Vec<F> = std::iter::successors(Some(folding_randomness), |r| Some(r.square()))
+        .take(values.len().trailing_zeros() as usize)
+        .collect();
+    fold_coset_rounds(values, x, &randomness)
+}
+
+/// Folds the values of a coset like `fold_coset`, with its own randomness for each binary fold.
+pub fn fold_coset_rounds<F: PrimeField>(values: &[F], x: F, folding_randomness: &[F]) -> F {
+    let mut values = values.to_vec();
+    let mut x = x;
+    let mut zeta = F::get_root_of_unity(values.len() as u64).unwrap();
+    for r in folding_randomness {
+        let half = values.len() / 2;
+        let mut y = x;
+        values = (0..half).map(|j| {
+            // values[j + half] is the evaluation at -y
+            let folded = (values[j] * (y + r) + values[j + half] * (y - r)) / y.double();
+            y *= zeta;
+            folded
+        }).collect();
+        x.square_in_place();
+        zeta.square_in_place();
+    }
+    values[0]
+}
+
+pub fn write_merkleproofs<H: DuplexSpongeInterface, F: PrimeField>(
+    polynomial: &Commited<PolynomialPoints<F>>,
+    leaf_index: u64,
+    prover_state: &mut spongefish::ProverState<H>,
+) -> Result<(), ProofError> {
+
+    let (leaf_val, path) = prove_leaf_index(polynomial, leaf_index)?;
+    prover_state.add_bytes(&leaf_val.into_bigint().to_bytes_be())?;
+    for p in path_to_bytes(path)? {
+        prover_state.add_bytes(&p)?;
+    }
+    Ok(())
+}
+
+/// Writes the values of a folding coset in coset order, then the single path above the block of leaves they form.
+fn write_merkleproofs_block<H: DuplexSpongeInterface, F: PrimeField>(
+    polynomial: &Commited<PolynomialPoints<F>>,
+    coset: &[u64],
+    prover_state: &mut spongefish::ProverState<H>,
+) -> Result<(), ProofError> {
+
+    for index in coset {
+        let value = leaf_value(polynomial, *index)?;
+        prover_state.add_bytes(&value.into_bigint().to_bytes_be())?;
+    }
+    let block_index = polynomial.leaf_position(coset[0]) as usize / coset.len();
+    let auth_path = polynomial.ptree.generate_block_proof(block_index, coset.len())?;
+    for p in digests_to_bytes(&auth_path)? {
+        prover_state.add_bytes(&p)?;
+    }
+    Ok(())
+}
+
+pub fn read_field_element<H: DuplexSpongeInterface, F: PrimeField>(
+    field_bytes: usize,
+    verifier_state: &mut spongefish::VerifierState<H>
+) -> Result<F, ProofError> {
+    let mut bytes = vec![0u8; field_bytes];
+    verifier_state.fill_next_bytes(&mut bytes)?;
+    Ok(F::from_be_bytes_mod_order(&bytes))
+}
+
+pub fn read_and_verify_merkle<H: DuplexSpongeInterface, F: PrimeField>(
+    leaf_value: F,
+    path_length: usize,
+    leaf_index: usize,
+    commitment: &[Root],
+    pedersen_config: &PedersenTreeConfig,
+    verifier_state: &mut spongefish::VerifierState<H>
+) -> Result<(), ProofError> {
+
+    let mut proof: Vec<[u8; 32]> = vec![];
+    for _ in 0..path_length {
+        proof.push(verifier_state.next_bytes::<32>().map_err(|_| ProofError::SerializationError)?);
+    }
+    let path = bytes_to_path(proof, leaf_index)?;
+    let verification_result = pedersen_config.verify_path(path, commitment, leaf_value)?;
+    if !verification_result {
+        return Err(ProofError::InvalidProof);
+    }
+    Ok(())
+}
+
+/// Reads the path above the block of leaves `block` and checks it against the commitment.
+fn read_and_verify_merkle_block<H: DuplexSpongeInterface, F: PrimeField>(
+    block: &[F],
+    block_index: usize,
+    auth_path_length: usize,
+    num_leaves: usize,
+    commitment: &[Root],
+    pedersen_config: &PedersenTreeConfig,
+    verifier_state: &mut spongefish::VerifierState<H>
+) -> Result<(), ProofError> {
+
+    let mut auth_path: Vec<Root> = vec![];
+    for _ in 0..auth_path_length {
+        auth_path.push(bytes_to_bls(verifier_state.next_bytes::<32>().map_err(|_| ProofError::SerializationError)?)?);
+    }
+    let verification_result = pedersen_config
+        .verify_block(block, block_index, &auth_path, commitment, num_leaves)?;
+    if !verification_result {
+        return Err(ProofError::InvalidProof);
+    }
+    Ok(())
+}
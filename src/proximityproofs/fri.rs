@@ -1,90 +1,1038 @@
-use ark_crypto_primitives::{sponge::Absorb};
 use ark_ec::{CurveGroup};
-use ark_ff::{BigInt, BigInteger, PrimeField};
+use ark_ff::{BigInteger, PrimeField};
 use spongefish::{codecs::arkworks_algebra::{FieldDomainSeparator, FieldToUnitDeserialize, GroupDomainSeparator, GroupToUnitDeserialize, GroupToUnitSerialize, UnitToField}, ByteDomainSeparator, BytesToUnitDeserialize, BytesToUnitSerialize, CommonUnitToBytes, DomainSeparator, DuplexSpongeInterface, ProofError, ProofResult, ProverState, UnitToBytes, VerifierState};
-use stark_tools::{commitable::{Commitable, Commited}, fields::{Field192, Goldilocks}, merkletree::{PedersenTreeConfig, Root}, polynomial::{Foldable2, Polynomial, PolynomialCoefficient, PolynomialPoints}};
-use crate::proximityproofs::{narg_proximityproof::{ProximityProofDomainSeparator, ProximityProofProver, ProximityProofVerifier}, utils::{bytes_to_bls, bytes_to_path, path_to_bytes, prove_leaf_index}};
-use std::{collections::HashMap, marker::PhantomData};
-use crate::proximityproofs::utils::bytes_to_bigints;
+use crate::{point::Point, commitable::{Commitable, Commited, LeafOrdering}, error::{StarkToolsError, StarkToolsResult}, merkletree::{MerkleCap, PedersenTreeConfig}, polynomial::{Foldable2, Polynomial, PolynomialCoefficient, PolynomialPoints}};
+use crate::proximityproofs::{narg_proximityproof::{ProximityProofDomainSeparator, ProximityProofProver, ProximityProofVerifier}, pcs::PolynomialCommitmentScheme, cosets::{fold_coset, read_and_verify_merkle, read_field_element, write_merkleproofs, CosetOpener}, fri_proof::{malformed, ByteReader, CosetOpening, FriProof, FriQuery, LeafOpening}, pow::{read_and_verify_pow, write_pow, MAX_POW_BITS, POW_CHALLENGE_BYTES, POW_NONCE_BYTES}, queries::{sample_queries, QUERY_SEED_BYTES}, transcript::{add_schema, TranscriptOp}, utils::{bytes_to_cap, cap_to_bytes, digests_to_bytes, DIGEST_BYTES}};
+use ark_std::rand::Rng;
+use std::{marker::PhantomData, time::Instant};
 
-type Field = Field192;
+/// Parameters of a FRI proof, independent of the field it runs over.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FriConfig {
+    /// The committed polynomial has degree below `degree_bound`.
+    pub degree_bound: u64,
+    /// Inverse rate of the code: codewords have `blowup * degree_bound` evaluations.
+    pub blowup: u64,
+    /// Each round divides the degree bound by `folding_factor`.
+    pub folding_factor: u64,
+    /// Number of queries checked in each round. With `QueryMode::Propagated`
+    /// round `i` checks the first `queries[i]` of the queries followed through the layers.
+    pub queries: Vec<u32>,
+    /// The prover stops folding once the degree drops to `final_degree`
+    /// and sends the remainder polynomial's coefficients instead of a last commitment.
+    pub final_degree: u64,
+    /// Leading zero bits of the proof of work the prover grinds before queries are sampled, 0 to skip it.
+    pub pow_bits: u32,
+    pub query_mode: QueryMode,
+}
+
+/// When the verifier's query positions are drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryMode {
+    /// Queries are sampled once after every commitment and each is followed down through all layers,
+    /// so every fold is checked against the coset opened in the next layer.
+    Propagated,
+    /// Each round samples fresh queries right after its own commitment and opens the fold of every queried coset.
+    PerRound,
+}
+
+/// A claimed evaluation `f(point) = value` at a point outside the evaluation domain.
+/// FRI on the quotient `(f(X) - value) / (X - point)`, degree corrected as in `OpeningQuotient`, shows that
+/// the committed codeword is close to a polynomial of degree below `degree_bound` that takes `value` at `point`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeepOpening<F> {
+    pub point: F,
+    pub value: F,
+}
+
+impl<F: PrimeField> DeepOpening<F> {
+    /// The quotient at `x` of an evaluation `y`, `None` if `x` is the opening point.
+    pub fn quotient_value(&self, x: F, y: F) -> Option<F> {
+        (x - self.point).inverse().map(|inverse| (y - self.value) * inverse)
+    }
+}
+
+/// What FRI runs on to prove openings of `f`: the quotient `q(X) = sum_k c_k * (f(X) - v_k) / (X - z_k)` over the
+/// weighted openings `(c_k, (z_k, v_k))`, times `1 + degree_correction * X`.
+/// `q` is a polynomial only if every opening is correct, since the poles at distinct `z_k` cannot cancel, and it has
+/// degree below `degree_bound - 1` exactly when `f` has degree below `degree_bound`. The correction factor raises that
+/// bound to the `degree_bound` FRI tests, so that an `f` of degree `degree_bound` is rejected.
+struct OpeningQuotient<F> {
+    openings: Vec<(F, DeepOpening<F>)>,
+    degree_correction: F,
+}
+
+impl<F: PrimeField> OpeningQuotient<F> {
+    /// The corrected quotient at `x` of an evaluation `y` of `f`, `None` if `x` is an opening point.
+    fn value(&self, x: F, y: F) -> Option<F> {
+        let quotient = self.openings.iter().try_fold(F::ZERO, |acc, (weight, opening)| Some(acc + *weight * opening.quotient_value(x, y)?))?;
+        Some(quotient * (F::ONE + self.degree_correction * x))
+    }
+
+    /// The corrected quotient on the domain of `codeword`.
+    fn codeword(&self, codeword: &PolynomialPoints<F>) -> Option<PolynomialPoints<F>> {
+        let mut quotient = codeword.clone();
+        for (x, point) in quotient.points.iter_mut() {
+            **point = Point::new(*x, self.value(*x, point.get_y())?);
+        }
+        Some(quotient)
+    }
+}
+
+impl FriConfig {
+    pub fn new(degree_bound: u64, blowup: u64, folding_factor: u64, queries: Vec<u32>, final_degree: u64) -> StarkToolsResult<Self> {
+        if !degree_bound.is_power_of_two() {
+            return Err(StarkToolsError::InvalidDegree(format!("degree bound {degree_bound} is not a power of two")));
+        }
+        if blowup < 2 || !blowup.is_power_of_two() {
+            return Err(StarkToolsError::InvalidRate(format!("blowup {blowup} is not a power of two above one")));
+        }
+        if folding_factor < 2 || !folding_factor.is_power_of_two() {
+            return Err(StarkToolsError::InvalidRate(format!("folding factor {folding_factor} is not a power of two above one")));
+        }
+        if degree_bound.checked_mul(blowup).is_none() {
+            return Err(StarkToolsError::InvalidRate(format!("a domain of {degree_bound} * {blowup} points does not fit in a u64")));
+        }
+        let remainder_bound = final_degree.checked_add(1).filter(|bound| bound.is_power_of_two() && *bound < degree_bound)
+            .ok_or_else(|| StarkToolsError::InvalidDegree(format!("final degree {final_degree} + 1 is not a power of two below the degree bound {degree_bound}")))?;
+        let fold_bits = degree_bound.trailing_zeros() - remainder_bound.trailing_zeros();
+        if !fold_bits.is_multiple_of(folding_factor.trailing_zeros()) {
+            return Err(StarkToolsError::InvalidConfig(format!("degree bound {degree_bound} does not fold down to {remainder_bound} by a factor of {folding_factor}")));
+        }
+        let num_rounds = (fold_bits / folding_factor.trailing_zeros()) as usize;
+        if queries.len() != num_rounds {
+            return Err(StarkToolsError::InvalidConfig(format!("expected query counts for {num_rounds} rounds, got {}", queries.len())));
+        }
+        if queries.contains(&0) {
+            return Err(StarkToolsError::InvalidConfig("every round needs at least one query".to_string()));
+        }
+        // queries are distinct folding cosets, of which round `i` has `domain_size(i + 1)`
+        let config = Self { degree_bound, blowup, folding_factor, queries, final_degree, pow_bits: 0, query_mode: QueryMode::Propagated };
+        if let Some(round) = (0..num_rounds).find(|&i| config.queries[i] as u64 > config.domain_size(i + 1)) {
+            return Err(StarkToolsError::InvalidConfig(format!("round {round} asks for {} queries but has only {} cosets", config.queries[round], config.domain_size(round + 1))));
+        }
+        Ok(config)
+    }
+
+    /// Each bit of grinding buys one bit of security at the cost of doubling the prover's search.
+    /// `FRIProtocol::new` rejects more than `MAX_POW_BITS`.
+    pub fn with_pow_bits(mut self, pow_bits: u32) -> Self {
+        self.pow_bits = pow_bits;
+        self
+    }
+
+    pub fn with_query_mode(mut self, query_mode: QueryMode) -> Self {
+        self.query_mode = query_mode;
+        self
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Size of the evaluation domain folded in `round`; `num_rounds()` gives the last codeword.
+    pub fn domain_size(&self, round: usize) -> u64 {
+        (self.degree_bound * self.blowup) >> (round as u32 * self.folding_factor.trailing_zeros())
+    }
+}
 
-pub struct FRIProtocol<G, H, const D: usize, const S: usize, const SBIGINT: usize> where 
+pub struct FRIProtocol<F, G, H> where 
+    F: PrimeField,
     G: CurveGroup,
     H: DuplexSpongeInterface,
     DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField> {
-    pub queries: [u32; D],
-    pub rate: u64,
+    pub config: FriConfig,
     pub pedersen_config: PedersenTreeConfig,
+    pub leaf_ordering: LeafOrdering,
     marker_type1: PhantomData<H>,
-    marker_type2: PhantomData<G>
+    marker_type2: PhantomData<G>,
+    marker_field: PhantomData<F>
 }
 
-impl<G, H, const D: usize, const S: usize, const SBIGINT: usize> FRIProtocol<G, H, D, S, SBIGINT> where 
+impl<F, G, H> FRIProtocol<F, G, H> where 
+    F: PrimeField,
     G: CurveGroup,
     H: DuplexSpongeInterface,
     DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField> {
     
-    pub fn new(queries: [u32; D], rate: u64, pedersen_config: PedersenTreeConfig) -> Self {
-        Self {
-            queries,
+    /// Fails when the field has no evaluation domain as large as the one `config` needs.
+    pub fn new(config: FriConfig, pedersen_config: PedersenTreeConfig) -> StarkToolsResult<Self> {
+        if config.domain_size(0).trailing_zeros() > F::TWO_ADICITY {
+            return Err(StarkToolsError::MissingRootOfUnity { order: config.domain_size(0) });
+        }
+        if config.pow_bits > MAX_POW_BITS {
+            return Err(StarkToolsError::InvalidConfig(format!("{} bits of proof of work, at most {MAX_POW_BITS} are supported", config.pow_bits)));
+        }
+        Ok(Self {
+            config,
             pedersen_config,
-            rate,
+            leaf_ordering: LeafOrdering::Natural,
             marker_type1: PhantomData,
-            marker_type2: PhantomData
+            marker_type2: PhantomData,
+            marker_field: PhantomData
+        })
+    }
+
+    /// With `LeafOrdering::BitReversed` every folding coset forms one block of leaves opened by a single Merkle path.
+    /// The committed input polynomial must use the same ordering.
+    pub fn with_leaf_ordering(mut self, leaf_ordering: LeafOrdering) -> Self {
+        self.leaf_ordering = leaf_ordering;
+        self
+    }
+
+    fn opener(&self) -> CosetOpener<'_, F> {
+        CosetOpener::new(&self.pedersen_config, self.leaf_ordering, self.config.folding_factor)
+    }
+
+    fn field_bytes(&self) -> usize {
+        self.opener().field_bytes()
+    }
+
+    fn cap_size(&self, num_leaves: u64) -> usize {
+        self.opener().cap_size(num_leaves)
+    }
+
+    fn path_length(&self, num_leaves: u64) -> usize {
+        self.opener().path_length(num_leaves)
+    }
+
+    /// Folds by the folding factor with a single challenge `r`,
+    /// as successive binary folds with `r`, `r^2`, `r^4`, ...
+    fn fold(&self, points: &PolynomialPoints<F>, folding_randomness: F) -> StarkToolsResult<PolynomialPoints<F>> {
+        let mut r = folding_randomness;
+        let mut folded = <PolynomialPoints<F> as Foldable2<F, 1>>::fold(points, self.config.blowup, r)?;
+        for _ in 1..self.config.folding_factor.trailing_zeros() {
+            r.square_in_place();
+            folded = <PolynomialPoints<F> as Foldable2<F, 1>>::fold(&folded, self.config.blowup, r)?;
+        }
+        Ok(folded)
+    }
+
+    /// The round a codeword with `degree_bound` joins a batch: the one folding a codeword of the same length.
+    /// `config.degree_bound` joins in round 0 and every further round takes a `folding_factor` smaller bound.
+    pub fn injection_round(&self, degree_bound: u64) -> Option<usize> {
+        let domain_size = degree_bound.checked_mul(self.config.blowup)?;
+        (0..self.config.num_rounds()).find(|&i| self.config.domain_size(i) == domain_size)
+    }
+
+    /// The injection round of every degree bound; at least one of them must have the full degree bound.
+    fn injection_rounds(&self, degree_bounds: &[u64]) -> StarkToolsResult<Vec<usize>> {
+        let rounds = degree_bounds.iter().map(|&degree_bound| {
+            self.injection_round(degree_bound).ok_or_else(|| StarkToolsError::InvalidDegree(format!("degree bound {degree_bound} does not match a folding round")))
+        }).collect::<StarkToolsResult<Vec<usize>>>()?;
+        if !rounds.contains(&0) {
+            return Err(StarkToolsError::InvalidDegree(format!("no polynomial has the degree bound {}", self.config.degree_bound)));
+        }
+        Ok(rounds)
+    }
+
+    /// Number of queries sampled in `QueryMode::Propagated`, enough for the busiest round.
+    fn propagated_queries(&self) -> usize {
+        self.config.queries.iter().max().copied().unwrap_or(0) as usize
+    }
+
+    fn query_sampling_schema(&self) -> Vec<TranscriptOp> {
+        let mut schema = vec![];
+        if self.config.pow_bits > 0 {
+            schema.push(TranscriptOp::Challenge { bytes: POW_CHALLENGE_BYTES, label: "proof of work challenge" });
+            schema.push(TranscriptOp::Message { bytes: POW_NONCE_BYTES, label: "proof of work nonce" });
+        }
+        // one seed, expanded into distinct cosets by `sample_queries`
+        schema.push(TranscriptOp::Challenge { bytes: QUERY_SEED_BYTES, label: "query seed" });
+        schema
+    }
+
+    /// Everything the transcript is laid out from, so that proofs of different configurations never share a domain separator.
+    fn session_identifier(&self, protocol: &str) -> String {
+        let path_lengths: Vec<usize> = (0..=self.config.num_rounds()).map(|i| self.path_length(self.config.domain_size(i))).collect();
+        format!(
+            "{protocol}, field of {} bits with modulus {}, hash {}, degree bound {}, blowup {}, folding factor {}, final degree {}, \
+            queries {:?} {:?}, proof of work {} bits, merkle cap height {}, path lengths {:?}, {:?} leaves",
+            F::MODULUS_BIT_SIZE,
+            F::MODULUS,
+            std::any::type_name::<H>(),
+            self.config.degree_bound,
+            self.config.blowup,
+            self.config.folding_factor,
+            self.config.final_degree,
+            self.config.queries,
+            self.config.query_mode,
+            self.config.pow_bits,
+            self.pedersen_config.cap_height,
+            path_lengths,
+            self.leaf_ordering
+        )
+    }
+
+    /// The messages of `prove_batch` for codewords with the given degree bounds, after the commitments.
+    /// Fails if a degree bound does not match a folding round, see `injection_round`.
+    pub fn batch_schema(&self, degree_bounds: &[u64]) -> StarkToolsResult<Vec<TranscriptOp>> {
+        Ok(self.rounds_schema(&self.injection_rounds(degree_bounds)?))
+    }
+
+    /// The messages of `prove_batch` for a single codeword with the full degree bound, after the commitment.
+    pub fn pp_schema(&self) -> Vec<TranscriptOp> {
+        self.rounds_schema(&[0])
+    }
+
+    /// The messages of `prove_batch` for codewords injected in the given rounds.
+    fn rounds_schema(&self, rounds: &[usize]) -> Vec<TranscriptOp> {
+        let s = self.field_bytes();
+        let mut schema = vec![];
+        if rounds.len() > 1 {
+            for _ in rounds {
+                schema.push(TranscriptOp::Challenge { bytes: s, label: "batching randomness" });
+            }
+        }
+        // the codeword folded in a round is opened in the previous fold and in every codeword injected there
+        let layer_size = |i: usize| rounds.iter().filter(|&&round| round == i).count() + if i == 0 { 0 } else { 1 };
+
+        for i in 0..self.config.num_rounds() {
+            schema.push(TranscriptOp::Challenge { bytes: s, label: "folding randomness" });
+            let max_index = self.config.domain_size(i);
+            let fold_max_index = self.config.domain_size(i + 1);
+            let last_round = i + 1 == self.config.num_rounds();
+            if last_round {
+                schema.push(TranscriptOp::Message { bytes: s * (self.config.final_degree as usize + 1), label: "remainder polynomial coefficients" });
+            } else {
+                schema.push(TranscriptOp::Message { bytes: DIGEST_BYTES * self.cap_size(fold_max_index), label: "fold commitment" });
+            }
+            if self.config.query_mode == QueryMode::PerRound {
+                schema.extend(self.query_sampling_schema());
+                for _ in 0..self.config.queries[i] as usize {
+                    for _ in 0..layer_size(i) {
+                        schema.extend(self.opener().coset_opening_schema(max_index));
+                    }
+                    // the last fold is checked against the remainder polynomial
+                    if !last_round {
+                        schema.push(TranscriptOp::Message { bytes: s, label: "fold leaf value" });
+                        for _ in 0..self.path_length(fold_max_index) {
+                            schema.push(TranscriptOp::Message { bytes: DIGEST_BYTES, label: "fold leaf proof" });
+                        }
+                    }
+                }
+            }
+        }
+        if self.config.query_mode == QueryMode::Propagated {
+            schema.extend(self.query_sampling_schema());
+            for i in 0..self.config.num_rounds() {
+                for _ in 0..self.config.queries[i] as usize * layer_size(i) {
+                    schema.extend(self.opener().coset_opening_schema(self.config.domain_size(i)));
+                }
+            }
         }
+        schema
+    }
+
+    /// The messages of `prove_openings` at `num_points` points, after the commitment.
+    pub fn openings_schema(&self, num_points: usize) -> Vec<TranscriptOp> {
+        let s = self.field_bytes();
+        let mut schema = vec![
+            TranscriptOp::Public { bytes: s * num_points, label: "opening points" },
+            TranscriptOp::Message { bytes: s * num_points, label: "opening evaluations" },
+        ];
+        if num_points > 1 {
+            schema.push(TranscriptOp::Challenge { bytes: s, label: "opening combination randomness" });
+        }
+        schema.push(TranscriptOp::Challenge { bytes: s, label: "degree correction randomness" });
+        schema.extend(self.pp_schema());
+        schema
+    }
+
+    /// The messages of `prove_deep`, after the commitment.
+    pub fn deep_schema(&self) -> Vec<TranscriptOp> {
+        let mut schema = vec![TranscriptOp::Challenge { bytes: self.field_bytes(), label: "out-of-domain point" }];
+        schema.extend(self.openings_schema(1));
+        schema
+    }
+
+    /// Grinds the proof of work if any, then squeezes the seed and samples `count` cosets of a domain of `max_index` points.
+    fn sample_queries_prover(&self, prover_state: &mut ProverState<H>, count: usize, max_index: u64) -> Result<Vec<u64>, ProofError> {
+        if self.config.pow_bits > 0 {
+            write_pow(self.config.pow_bits, prover_state)?;
+        }
+        let query_seed = prover_state.challenge_bytes::<QUERY_SEED_BYTES>()?;
+        Ok(sample_queries(&query_seed, count, max_index / self.config.folding_factor))
+    }
+
+    fn sample_queries_verifier(&self, verifier_state: &mut VerifierState<H>, count: usize, max_index: u64) -> Result<Vec<u64>, ProofError> {
+        if self.config.pow_bits > 0 {
+            read_and_verify_pow(self.config.pow_bits, verifier_state)?;
+        }
+        let query_seed = verifier_state.challenge_bytes::<QUERY_SEED_BYTES>()?;
+        Ok(sample_queries(&query_seed, count, max_index / self.config.folding_factor))
     }
 }
 
 
-impl<H, G: CurveGroup, const D: usize, const S: usize, const SBIGINT: usize> ProximityProofDomainSeparator<G, H> for FRIProtocol<G, H, D, S, SBIGINT> where
+impl<F, G, H> FRIProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>
+{
+    /// Domain separator for `prove_batch`/`verify_batch` over committed codewords with the given degree bounds.
+    pub fn new_batch_pp_proof(&self, degree_bounds: &[u64]) -> StarkToolsResult<DomainSeparator<H>> {
+        let ds = DomainSeparator::new(&self.session_identifier("FRI proximity proof"));
+        let ds = self.add_batch_statement(ds, degree_bounds);
+        self.add_batch_domsep(ds, degree_bounds)
+    }
+
+    /// Domain separator for `prove_deep`/`verify_deep`.
+    pub fn new_deep_proof(&self) -> DomainSeparator<H> {
+        let ds = DomainSeparator::new(&self.session_identifier("DEEP-FRI proximity proof"));
+        let ds = self.add_batch_statement(ds, &[self.config.degree_bound]);
+        add_schema(ds, &self.deep_schema())
+    }
+
+    /// Extends `ds` with `prove_openings`/`verify_openings` at `num_points` points.
+    /// The commitment must already be in the transcript.
+    pub fn add_openings_domsep(&self, ds: DomainSeparator<H>, num_points: usize) -> DomainSeparator<H> {
+        add_schema(ds, &self.openings_schema(num_points))
+    }
+
+    fn add_batch_statement(&self, ds: DomainSeparator<H>, degree_bounds: &[u64]) -> DomainSeparator<H> {
+        let mut ds = ds;
+        for degree_bound in degree_bounds {
+            ds = ds.add_bytes(DIGEST_BYTES * self.cap_size(degree_bound * self.config.blowup), "public commitment (C)");
+        }
+        ds.ratchet()
+    }
+
+    fn add_batch_domsep(&self, ds: DomainSeparator<H>, degree_bounds: &[u64]) -> StarkToolsResult<DomainSeparator<H>> {
+        Ok(add_schema(ds, &self.batch_schema(degree_bounds)?))
+    }
+}
+
+impl<F, G, H> ProximityProofDomainSeparator<G, H> for FRIProtocol<F, G, H> where
+    F: PrimeField,
     G: CurveGroup,
     H: DuplexSpongeInterface,
     DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>
 {
     fn new_pp_proof(&self) -> DomainSeparator<H> {
-        let ds = DomainSeparator::new("FRI proximity proof");
-        let ds = self.add_pp_statement(ds);
-        self.add_pp_domsep(ds)
+        let ds = DomainSeparator::new(&self.session_identifier("FRI proximity proof"));
+        self.add_pp_domsep(self.add_pp_statement(ds))
     }
 
     fn add_pp_statement(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
-        ds.add_bytes(32, "public commitment (C)")
-            // .add_points(1, "public key (X)")
-            .ratchet()
+        self.add_batch_statement(ds, &[self.config.degree_bound])
     }
 
     fn add_pp_domsep(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
-        // self.add_bytes(32, "fake commitment (K)")
-        let mut ds = ds;
-        
-        for i in 0..D {
-            ds = ds.challenge_bytes(S, "folding randomness");
-            ds = ds.add_bytes(32, "fold commitment");
-            let path_length = (D - i) + (self.rate as f64).log2().ceil() as usize;
-            println!("path_length: {path_length}");
-            for _ in 0..self.queries[i] as usize {
-                ds = ds.challenge_bytes(2, "round query index");
-                ds = ds.add_bytes(S, "leaf0 value");
-                for _ in 0..path_length { // it must be 3*path_length - 1
-                    ds = ds.add_bytes(32, "leaf0 proof");
+        add_schema(ds, &self.pp_schema())
+    }
+}
+
+impl<F, G, H> FRIProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>
+{
+    /// Proves that every codeword in `polynomials` is close to low degree, by running FRI on a random
+    /// linear combination of them. A codeword with a smaller degree bound is added to the combination
+    /// in its `injection_round`. The transcript must come from `new_batch_pp_proof` with the polynomials'
+    /// degree bounds and all the commitments added as public bytes.
+    pub fn prove_batch<'b>(
+        &self,
+        prover_state: &'b mut ProverState<H>,
+        polynomials: &[Commited<PolynomialPoints<F>>],
+    ) -> StarkToolsResult<&'b [u8]> {
+        self.prove_folding(prover_state, polynomials, None)?;
+        Ok(prover_state.narg_string())
+    }
+
+    /// Runs the batched FRI rounds. With a `quotient`, the codeword folded in round 0 is the quotient
+    /// of the combination of the full-degree polynomials rather than the combination itself.
+    fn prove_folding(
+        &self,
+        prover_state: &mut ProverState<H>,
+        polynomials: &[Commited<PolynomialPoints<F>>],
+        quotient: Option<&OpeningQuotient<F>>,
+    ) -> StarkToolsResult<()> {
+        if let Some(polynomial) = polynomials.iter().find(|p| p.ordering != self.leaf_ordering) {
+            return Err(StarkToolsError::InvalidConfig(format!("polynomial committed with {:?} leaves, expected {:?}", polynomial.ordering, self.leaf_ordering)));
+        }
+        let degree_bounds: Vec<u64> = polynomials.iter().map(|p| p.data.degree + 1).collect();
+        let rounds = self.injection_rounds(&degree_bounds)?;
+        let batching_randomness = if polynomials.len() > 1 {
+            let mut batching_bytes = vec![0u8; self.field_bytes()];
+            (0..polynomials.len()).map(|_| {
+                prover_state.fill_challenge_bytes(&mut batching_bytes)?;
+                Ok(F::from_be_bytes_mod_order(&batching_bytes))
+            }).collect::<ProofResult<Vec<F>>>()?
+        } else {
+            vec![F::ONE]
+        };
+        let rounds = &rounds;
+        let injected = |i: usize| (0..polynomials.len()).filter(move |&j| rounds[j] == i);
+
+        let _proof_span = tracing::info_span!(
+            "fri_prove",
+            degree_bound = self.config.degree_bound,
+            blowup = self.config.blowup,
+            folding_factor = self.config.folding_factor,
+            num_rounds = self.config.num_rounds(),
+            num_polynomials = polynomials.len(),
+            num_openings = quotient.map_or(0, |quotient| quotient.openings.len()),
+            query_mode = ?self.config.query_mode,
+        ).entered();
+        // folds[i] is committed in round `i` and folded in round `i + 1`
+        let mut folds: Vec<Commited<PolynomialPoints<F>>> = vec![];
+        let mut folding_bytes = vec![0u8; self.field_bytes()];
+        for i in 0..self.config.num_rounds() {
+            let _round_span = tracing::debug_span!("fri_round", round = i, domain_size = self.config.domain_size(i)).entered();
+            prover_state.fill_challenge_bytes(&mut folding_bytes)?;
+            let folding_randomness = F::from_be_bytes_mod_order(&folding_bytes);
+            let mut combined = combine(
+                folds.last().map(|fold| (&fold.data, F::ONE)).into_iter()
+                    .chain(injected(i).map(|j| (&polynomials[j].data, batching_randomness[j])))
+            )?;
+            if let (0, Some(quotient)) = (i, quotient) {
+                combined = quotient.codeword(&combined)
+                    .ok_or_else(|| StarkToolsError::InvalidDomain("an opening point lies in the evaluation domain".to_string()))?;
+            }
+            let folded = self.fold(&combined, folding_randomness)?;
+            let last_round = i + 1 == self.config.num_rounds();
+            let fold = if last_round {
+                let remainder = folded.ifft(self.config.blowup)?;
+                for coefficient in &remainder.coefficients {
+                    prover_state.add_bytes(&coefficient.into_bigint().to_bytes_be())?;
+                }
+                None
+            } else {
+                let start = Instant::now();
+                let fold = folded.commit_with_ordering(&self.pedersen_config, self.leaf_ordering)?;
+                let commitment: MerkleCap = fold.ptree.cap();
+                tracing::debug!(commit_time_us = start.elapsed().as_micros() as u64, "fold committed");
+                prover_state.add_bytes(&cap_to_bytes(&commitment))?;
+                Some(fold)
+            };
+            
+            if self.config.query_mode == QueryMode::PerRound {
+                let max_index = self.config.domain_size(i);
+                let layer: Vec<&Commited<PolynomialPoints<F>>> = folds.last().into_iter().chain(injected(i).map(|j| &polynomials[j])).collect();
+                for fold_leaf_index in self.sample_queries_prover(prover_state, self.config.queries[i] as usize, max_index)? {
+                    for polynomial in &layer {
+                        self.opener().write_coset_opening(polynomial, fold_leaf_index, max_index, prover_state)?;
+                    }
+                    if let Some(fold) = &fold {
+                        write_merkleproofs(fold, fold_leaf_index, prover_state)?;
+                    }
+                }
+                tracing::debug!(queries = self.config.queries[i], proof_bytes = prover_state.narg_string().len(), "queries opened");
+            }
+            folds.extend(fold);
+        }
+
+        if self.config.query_mode == QueryMode::Propagated {
+            let queries = self.sample_queries_prover(prover_state, self.propagated_queries(), self.config.domain_size(0))?;
+            for i in 0..self.config.num_rounds() {
+                let _round_span = tracing::debug_span!("fri_round", round = i, domain_size = self.config.domain_size(i)).entered();
+                let max_index = self.config.domain_size(i);
+                let layer: Vec<&Commited<PolynomialPoints<F>>> = folds[..i].last().into_iter().chain(injected(i).map(|j| &polynomials[j])).collect();
+                for query in queries.iter().take(self.config.queries[i] as usize) {
+                    // a query keeps its index as the codeword shrinks, so it lands in the coset of `query mod fold domain`
+                    for polynomial in &layer {
+                        self.opener().write_coset_opening(polynomial, query % self.config.domain_size(i + 1), max_index, prover_state)?;
+                    }
+                }
+                tracing::debug!(queries = self.config.queries[i], proof_bytes = prover_state.narg_string().len(), "queries opened");
+            }
+        }
+        tracing::info!(proof_bytes = prover_state.narg_string().len(), "FRI proof done");
+        Ok(())
+    }
+
+    /// Proves the evaluation of the committed polynomial at an out-of-domain point drawn from the transcript,
+    /// by running FRI on the degree corrected quotient `(f(X) - f(z)) / (X - z) * (1 + r * X)`. The transcript must come from `new_deep_proof`
+    /// with the commitment added as public bytes. Returns the opening, whose value is also in the proof.
+    pub fn prove_deep(
+        &self,
+        prover_state: &mut ProverState<H>,
+        polynomial: &Commited<PolynomialPoints<F>>,
+    ) -> StarkToolsResult<DeepOpening<F>> {
+        let mut point_bytes = vec![0u8; self.field_bytes()];
+        prover_state.fill_challenge_bytes(&mut point_bytes)?;
+        let point = F::from_be_bytes_mod_order(&point_bytes);
+        let value = self.prove_openings(prover_state, polynomial, &[point])?[0];
+        Ok(DeepOpening { point, value })
+    }
+
+    /// Proves the evaluations of the committed polynomial at `points`, which must lie outside the evaluation domain,
+    /// by running FRI on a random combination of the quotients `(f(X) - f(z_k)) / (X - z_k)`, degree corrected as in `OpeningQuotient`.
+    /// The points are absorbed before the evaluations, so the proof is bound to them.
+    /// The transcript must come from `add_openings_domsep` with as many points. Returns the evaluations.
+    pub fn prove_openings(
+        &self,
+        prover_state: &mut ProverState<H>,
+        polynomial: &Commited<PolynomialPoints<F>>,
+        points: &[F],
+    ) -> StarkToolsResult<Vec<F>> {
+        let values = points.iter().map(|&point| polynomial.data.evaluate(point)).collect::<StarkToolsResult<Vec<F>>>()?;
+        for point in points {
+            prover_state.public_bytes(&point.into_bigint().to_bytes_be())?;
+        }
+        for value in &values {
+            prover_state.add_bytes(&value.into_bigint().to_bytes_be())?;
+        }
+        let weights = if points.len() > 1 {
+            let mut weight_bytes = vec![0u8; self.field_bytes()];
+            prover_state.fill_challenge_bytes(&mut weight_bytes)?;
+            powers(F::from_be_bytes_mod_order(&weight_bytes), points.len())
+        } else {
+            vec![F::ONE; points.len()]
+        };
+        let openings: Vec<(F, DeepOpening<F>)> = weights.into_iter().zip(points.iter().zip(&values))
+            .map(|(weight, (&point, &value))| (weight, DeepOpening { point, value }))
+            .collect();
+        let mut correction_bytes = vec![0u8; self.field_bytes()];
+        prover_state.fill_challenge_bytes(&mut correction_bytes)?;
+        let quotient = OpeningQuotient { openings, degree_correction: F::from_be_bytes_mod_order(&correction_bytes) };
+        self.prove_folding(prover_state, std::slice::from_ref(polynomial), Some(&quotient))?;
+        Ok(values)
+    }
+
+    /// Verifies a `prove_deep` proof against `commitment` and returns the opening it proves.
+    /// The quotient values are recomputed from the opened leaves of the committed codeword.
+    pub fn verify_deep(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &MerkleCap,
+    ) -> StarkToolsResult<DeepOpening<F>> {
+        let mut point_bytes = vec![0u8; self.field_bytes()];
+        verifier_state.fill_challenge_bytes(&mut point_bytes)?;
+        let point = F::from_be_bytes_mod_order(&point_bytes);
+        let value = self.read_openings(verifier_state, commitment, &[point])?[0];
+        Ok(DeepOpening { point, value })
+    }
+
+    /// Verifies a `prove_openings` proof that the polynomial committed to by `commitment` takes `values` at `points`.
+    pub fn verify_openings(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &MerkleCap,
+        points: &[F],
+        values: &[F],
+    ) -> StarkToolsResult<()> {
+        if self.read_openings(verifier_state, commitment, points)? != values {
+            return Err(StarkToolsError::InvalidProof);
+        }
+        Ok(())
+    }
+
+    /// Verifies a `prove_openings` proof and returns the evaluations it proves.
+    fn read_openings(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &MerkleCap,
+        points: &[F],
+    ) -> StarkToolsResult<Vec<F>> {
+        for point in points {
+            verifier_state.public_bytes(&point.into_bigint().to_bytes_be())?;
+        }
+        let values = points.iter()
+            .map(|_| read_field_element(self.field_bytes(), verifier_state))
+            .collect::<Result<Vec<F>, ProofError>>()?;
+        let weights = if points.len() > 1 {
+            let mut weight_bytes = vec![0u8; self.field_bytes()];
+            verifier_state.fill_challenge_bytes(&mut weight_bytes)?;
+            powers(F::from_be_bytes_mod_order(&weight_bytes), points.len())
+        } else {
+            vec![F::ONE; points.len()]
+        };
+        let openings: Vec<(F, DeepOpening<F>)> = weights.into_iter().zip(points.iter().zip(&values))
+            .map(|(weight, (&point, &value))| (weight, DeepOpening { point, value }))
+            .collect();
+        let mut correction_bytes = vec![0u8; self.field_bytes()];
+        verifier_state.fill_challenge_bytes(&mut correction_bytes)?;
+        let quotient = OpeningQuotient { openings, degree_correction: F::from_be_bytes_mod_order(&correction_bytes) };
+        self.verify_folding(verifier_state, std::slice::from_ref(commitment), &[self.config.degree_bound], Some(&quotient))?;
+        Ok(values)
+    }
+
+    /// Verifies a `prove_batch` proof for the codewords committed to by `commitments`, with the given degree bounds.
+    pub fn verify_batch(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitments: &[MerkleCap],
+        degree_bounds: &[u64],
+    ) -> StarkToolsResult<()> {
+        self.verify_folding(verifier_state, commitments, degree_bounds, None)
+    }
+
+    fn verify_folding(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitments: &[MerkleCap],
+        degree_bounds: &[u64],
+        quotient: Option<&OpeningQuotient<F>>,
+    ) -> StarkToolsResult<()> {
+        if commitments.len() != degree_bounds.len() {
+            return Err(StarkToolsError::InvalidConfig(format!("{} commitments for {} degree bounds", commitments.len(), degree_bounds.len())));
+        }
+        let rounds = self.injection_rounds(degree_bounds)?;
+        let batching_randomness = if commitments.len() > 1 {
+            let mut batching_bytes = vec![0u8; self.field_bytes()];
+            (0..commitments.len()).map(|_| {
+                verifier_state.fill_challenge_bytes(&mut batching_bytes)?;
+                Ok(F::from_be_bytes_mod_order(&batching_bytes))
+            }).collect::<StarkToolsResult<Vec<F>>>()?
+        } else {
+            vec![F::ONE]
+        };
+        let rounds = &rounds;
+        let injected = |i: usize| (0..commitments.len()).filter(move |&j| rounds[j] == i);
+
+        // layers[i] holds the commitments opened in round `i`, the previous fold first,
+        // and coefficients[i] how their values are combined
+        let mut layers: Vec<Vec<MerkleCap>> = vec![injected(0).map(|j| commitments[j].clone()).collect()];
+        let mut coefficients: Vec<Vec<F>> = vec![injected(0).map(|j| batching_randomness[j]).collect()];
+        let mut folding_randomness: Vec<F> = vec![];
+        let mut remainder = None;
+        let mut fold_r_bytes = vec![0u8; self.field_bytes()];
+        for i in 0..self.config.num_rounds() {
+            let max_index = self.config.domain_size(i);
+            let fold_max_index = self.config.domain_size(i + 1);
+            verifier_state.fill_challenge_bytes(&mut fold_r_bytes)?;
+            folding_randomness.push(F::from_be_bytes_mod_order(&fold_r_bytes));
+            let last_round = i + 1 == self.config.num_rounds();
+            if last_round {
+                let coefficients = (0..=self.config.final_degree)
+                    .map(|_| read_field_element(self.field_bytes(), verifier_state))
+                    .collect::<Result<Vec<F>, _>>()?;
+                remainder = Some(PolynomialCoefficient::<F>::new(self.config.final_degree, coefficients)?);
+            } else {
+                let mut fold_commitment_bytes = vec![0; DIGEST_BYTES * self.cap_size(fold_max_index)];
+                verifier_state.fill_next_bytes(&mut fold_commitment_bytes)?;
+                layers.push(std::iter::once(bytes_to_cap(&fold_commitment_bytes)?).chain(injected(i + 1).map(|j| commitments[j].clone())).collect());
+                coefficients.push(std::iter::once(F::ONE).chain(injected(i + 1).map(|j| batching_randomness[j])).collect());
+            }
+
+            if self.config.query_mode == QueryMode::PerRound {
+                let omega = F::get_root_of_unity(max_index).ok_or(StarkToolsError::MissingRootOfUnity { order: max_index })?;
+                let fold_leaf_indices = self.sample_queries_verifier(verifier_state, self.config.queries[i] as usize, max_index)
+                    .map_err(check_failed(i, None))?;
+                for (j, fold_leaf_index) in fold_leaf_indices.into_iter().enumerate() {
+                    let layer_values = self.read_layer_opening(verifier_state, &layers[i], fold_leaf_index, max_index)
+                        .map_err(check_failed(i, Some(j)))?;
+                    let x = omega.pow([fold_leaf_index]);
+                    let fold_leaf_value = match &remainder {
+                        Some(remainder) => remainder.evaluate(x.pow([self.config.folding_factor])),
+                        None => {
+                            let fold_leaf_value = read_field_element(self.field_bytes(), verifier_state)?;
+                            read_and_verify_merkle(
+                                fold_leaf_value, 
+                                self.path_length(fold_max_index), 
+                                self.leaf_ordering.leaf_position(fold_leaf_index, fold_max_index) as usize, 
+                                &layers[i + 1][0], 
+                                &self.pedersen_config, 
+                                verifier_state
+                            ).map_err(check_failed(i, Some(j)))?;
+                            fold_leaf_value
+                        }
+                    };
+                    let coset_values = self.layer_coset(&layer_values, &coefficients[i], i, quotient, fold_leaf_index, max_index)
+                        .map_err(check_failed(i, Some(j)))?;
+                    if fold_leaf_value != fold_coset(&coset_values, x, folding_randomness[i]) {
+                        return Err(StarkToolsError::VerificationFailed { round: i, query: Some(j) });
+                    }
+                }
+            }
+        }
+        let remainder = remainder.ok_or_else(|| StarkToolsError::InvalidConfig("FRI needs at least one folding round".to_string()))?;
+
+        if self.config.query_mode == QueryMode::Propagated {
+            let queries = self.sample_queries_verifier(verifier_state, self.propagated_queries(), self.config.domain_size(0))
+                .map_err(check_failed(0, None))?;
+            // the value each query's fold took in the previous round, if it was checked there
+            let mut expected: Vec<Option<F>> = vec![None; queries.len()];
+            for i in 0..self.config.num_rounds() {
+                let max_index = self.config.domain_size(i);
+                let fold_max_index = self.config.domain_size(i + 1);
+                let omega = F::get_root_of_unity(max_index).ok_or(StarkToolsError::MissingRootOfUnity { order: max_index })?;
+                let mut folded_values = vec![None; queries.len()];
+                for (j, query) in queries.iter().enumerate().take(self.config.queries[i] as usize) {
+                    let fold_leaf_index = query % fold_max_index;
+                    let layer_values = self.read_layer_opening(verifier_state, &layers[i], fold_leaf_index, max_index)
+                        .map_err(check_failed(i, Some(j)))?;
+                    // the previous fold is the first commitment of every layer after the first
+                    if expected[j].is_some_and(|value| layer_values[0][((query % max_index) / fold_max_index) as usize] != value) {
+                        return Err(StarkToolsError::VerificationFailed { round: i, query: Some(j) });
+                    }
+                    let x = omega.pow([fold_leaf_index]);
+                    let coset_values = self.layer_coset(&layer_values, &coefficients[i], i, quotient, fold_leaf_index, max_index)
+                        .map_err(check_failed(i, Some(j)))?;
+                    let folded = fold_coset(&coset_values, x, folding_randomness[i]);
+                    if i + 1 == self.config.num_rounds() {
+                        if folded != remainder.evaluate(x.pow([self.config.folding_factor])) {
+                            return Err(StarkToolsError::VerificationFailed { round: i, query: Some(j) });
+                        }
+                    } else {
+                        folded_values[j] = Some(folded);
+                    }
+                }
+                expected = folded_values;
+            }
+        }
+        Ok(())
+    }
+
+    /// The coset of the codeword folded in `round`, from the values opened in each commitment of its layer.
+    fn layer_coset(
+        &self,
+        layer_values: &[Vec<F>],
+        coefficients: &[F],
+        round: usize,
+        quotient: Option<&OpeningQuotient<F>>,
+        fold_leaf_index: u64,
+        max_index: u64
+    ) -> Result<Vec<F>, ProofError> {
+        let combined = combine_values(layer_values, coefficients);
+        let Some(quotient) = quotient.filter(|_| round == 0) else {
+            return Ok(combined);
+        };
+        let omega = F::get_root_of_unity(max_index).ok_or(ProofError::InvalidProof)?;
+        let points = self.opener().coset(fold_leaf_index, max_index).into_iter().map(|index| omega.pow([index]));
+        points.zip(combined)
+            .map(|(x, y)| quotient.value(x, y).ok_or(ProofError::InvalidProof))
+            .collect()
+    }
+
+    /// Opens the coset folding into `fold_leaf_index` in every commitment of a layer.
+    fn read_layer_opening(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        layer: &[MerkleCap],
+        fold_leaf_index: u64,
+        max_index: u64
+    ) -> Result<Vec<Vec<F>>, ProofError> {
+        layer.iter()
+            .map(|commitment| self.opener().read_coset_opening(verifier_state, commitment, fold_leaf_index, max_index))
+            .collect()
+    }
+}
+
+impl<F, G, H> FRIProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>
+{
+    /// Proves like `prove_batch` for a single codeword and returns the proof as a `FriProof`.
+    /// The NARG string stays available from `prover_state`.
+    pub fn prove_proof(
+        &self,
+        prover_state: &mut ProverState<H>,
+        polynomial: &Commited<PolynomialPoints<F>>,
+    ) -> StarkToolsResult<FriProof<F>> {
+        let narg = self.prove_batch(prover_state, std::slice::from_ref(polynomial))?;
+        self.parse_proof(narg)
+    }
+
+    /// Splits the NARG string of a proof for a single codeword, as made by `prove`, into its messages.
+    pub fn parse_proof(&self, narg: &[u8]) -> StarkToolsResult<FriProof<F>> {
+        let mut reader = ByteReader::new(narg);
+        let num_rounds = self.config.num_rounds();
+        let mut proof = FriProof {
+            fold_commitments: vec![],
+            final_polynomial: vec![],
+            pow_nonces: vec![],
+            queries: vec![vec![]; num_rounds],
+        };
+        for i in 0..num_rounds {
+            let last_round = i + 1 == num_rounds;
+            if last_round {
+                proof.final_polynomial = (0..=self.config.final_degree).map(|_| reader.field_element()).collect::<StarkToolsResult<Vec<F>>>()?;
+            } else {
+                proof.fold_commitments.push(reader.cap(self.cap_size(self.config.domain_size(i + 1)))?);
+            }
+            if self.config.query_mode == QueryMode::PerRound {
+                if self.config.pow_bits > 0 {
+                    proof.pow_nonces.push(reader.u64_be()?);
+                }
+                for _ in 0..self.config.queries[i] {
+                    let coset = self.parse_coset_opening(&mut reader, self.config.domain_size(i))?;
+                    let fold_leaf = if last_round {
+                        None
+                    } else {
+                        Some(LeafOpening { value: reader.field_element()?, path: reader.digests(self.path_length(self.config.domain_size(i + 1)))? })
+                    };
+                    proof.queries[i].push(FriQuery { coset, fold_leaf });
+                }
+            }
+        }
+        if self.config.query_mode == QueryMode::Propagated {
+            if self.config.pow_bits > 0 {
+                proof.pow_nonces.push(reader.u64_be()?);
+            }
+            for i in 0..num_rounds {
+                for _ in 0..self.config.queries[i] {
+                    let coset = self.parse_coset_opening(&mut reader, self.config.domain_size(i))?;
+                    proof.queries[i].push(FriQuery { coset, fold_leaf: None });
+                }
+            }
+        }
+        reader.finish()?;
+        Ok(proof)
+    }
+
+    fn parse_coset_opening(&self, reader: &mut ByteReader, max_index: u64) -> StarkToolsResult<CosetOpening<F>> {
+        let folding_factor = self.config.folding_factor as usize;
+        match self.leaf_ordering {
+            LeafOrdering::Natural => {
+                let mut opening = CosetOpening { values: vec![], paths: vec![] };
+                for _ in 0..folding_factor {
+                    opening.values.push(reader.field_element()?);
+                    opening.paths.push(reader.digests(self.path_length(max_index))?);
+                }
+                Ok(opening)
+            }
+            LeafOrdering::BitReversed => {
+                let values = (0..folding_factor).map(|_| reader.field_element()).collect::<StarkToolsResult<Vec<F>>>()?;
+                let path = reader.digests(self.opener().block_path_length(max_index))?;
+                Ok(CosetOpening { values, paths: vec![path] })
+            }
+        }
+    }
+
+    /// Writes `proof` back into the NARG string `verify` reads, failing if its shape does not match the config.
+    pub fn proof_to_narg(&self, proof: &FriProof<F>) -> StarkToolsResult<Vec<u8>> {
+        let num_rounds = self.config.num_rounds();
+        let expected_nonces = match (self.config.pow_bits, self.config.query_mode) {
+            (0, _) => 0,
+            (_, QueryMode::PerRound) => num_rounds,
+            (_, QueryMode::Propagated) => 1,
+        };
+        expect_length("fold commitments", proof.fold_commitments.len(), num_rounds - 1)?;
+        expect_length("final polynomial coefficients", proof.final_polynomial.len(), self.config.final_degree as usize + 1)?;
+        expect_length("proof of work nonces", proof.pow_nonces.len(), expected_nonces)?;
+        expect_length("rounds of queries", proof.queries.len(), num_rounds)?;
+
+        let mut narg = vec![];
+        let mut nonces = proof.pow_nonces.iter();
+        for i in 0..num_rounds {
+            let last_round = i + 1 == num_rounds;
+            if last_round {
+                for coefficient in &proof.final_polynomial {
+                    narg.extend(coefficient.into_bigint().to_bytes_be());
+                }
+            } else {
+                let cap = &proof.fold_commitments[i];
+                expect_length("fold commitment digests", cap.len(), self.cap_size(self.config.domain_size(i + 1)))?;
+                narg.extend(cap_to_bytes(cap));
+            }
+            if self.config.query_mode == QueryMode::PerRound {
+                if let Some(nonce) = nonces.next() {
+                    narg.extend(nonce.to_be_bytes());
+                }
+                expect_length("queries", proof.queries[i].len(), self.config.queries[i] as usize)?;
+                for query in &proof.queries[i] {
+                    self.write_coset_opening(&mut narg, &query.coset, self.config.domain_size(i))?;
+                    match (&query.fold_leaf, last_round) {
+                        (None, true) => {}
+                        (Some(leaf), false) => {
+                            expect_length("fold leaf path digests", leaf.path.len(), self.path_length(self.config.domain_size(i + 1)))?;
+                            narg.extend(leaf.value.into_bigint().to_bytes_be());
+                            narg.extend(digests_to_bytes(&leaf.path)?.concat());
+                        }
+                        _ => return Err(malformed(format!("round {i} expects a fold leaf in every query but the last round's"))),
+                    }
                 }
-                ds = ds.add_bytes(S, "leaf1 value");
-                for _ in 0..path_length { // it must be 3*path_length - 1
-                    ds = ds.add_bytes(32, "leaf1 proof");
+            }
+        }
+        if self.config.query_mode == QueryMode::Propagated {
+            if let Some(nonce) = nonces.next() {
+                narg.extend(nonce.to_be_bytes());
+            }
+            for i in 0..num_rounds {
+                expect_length("queries", proof.queries[i].len(), self.config.queries[i] as usize)?;
+                for query in &proof.queries[i] {
+                    if query.fold_leaf.is_some() {
+                        return Err(malformed("propagated queries open no fold leaves"));
+                    }
+                    self.write_coset_opening(&mut narg, &query.coset, self.config.domain_size(i))?;
+                }
+            }
+        }
+        Ok(narg)
+    }
+
+    fn write_coset_opening(&self, narg: &mut Vec<u8>, opening: &CosetOpening<F>, max_index: u64) -> StarkToolsResult<()> {
+        expect_length("coset values", opening.values.len(), self.config.folding_factor as usize)?;
+        match self.leaf_ordering {
+            LeafOrdering::Natural => {
+                expect_length("coset paths", opening.paths.len(), self.config.folding_factor as usize)?;
+                for (value, path) in opening.values.iter().zip(&opening.paths) {
+                    expect_length("coset path digests", path.len(), self.path_length(max_index))?;
+                    narg.extend(value.into_bigint().to_bytes_be());
+                    narg.extend(digests_to_bytes(path)?.concat());
                 }
-                ds = ds.add_bytes(S, "fold leaf value");
-                for _ in 0..path_length-1 { // it must be 3*path_length - 1
-                    ds = ds.add_bytes(32, "fold leaf proof");
+            }
+            LeafOrdering::BitReversed => {
+                expect_length("coset paths", opening.paths.len(), 1)?;
+                expect_length("coset path digests", opening.paths[0].len(), self.opener().block_path_length(max_index))?;
+                for value in &opening.values {
+                    narg.extend(value.into_bigint().to_bytes_be());
                 }
+                narg.extend(digests_to_bytes(&opening.paths[0])?.concat());
             }
         }
-        ds
+        Ok(())
     }
 }
 
-impl<'b, H, G, P, F, Raw, const D: usize, const S: usize, const SBIGINT: usize> 
-ProximityProofProver<'b, H, G, P, F, Raw> for FRIProtocol<G, H, D, S, SBIGINT> where 
+fn expect_length(what: &str, length: usize, expected: usize) -> StarkToolsResult<()> {
+    if length != expected {
+        return Err(malformed(format!("expected {expected} {what}, got {length}")));
+    }
+    Ok(())
+}
+
+/// Attributes a rejected check to `round` and `query`; transcript errors pass through unchanged.
+fn check_failed(round: usize, query: Option<usize>) -> impl Fn(ProofError) -> StarkToolsError {
+    move |e| match e {
+        ProofError::InvalidProof => StarkToolsError::VerificationFailed { round, query },
+        e => e.into(),
+    }
+}
+
+/// The codeword `sum_i c_i * p_i` over the terms `(p_i, c_i)`, on the domain of the first polynomial.
+fn combine<'a, F: PrimeField>(terms: impl Iterator<Item = (&'a PolynomialPoints<F>, F)>) -> StarkToolsResult<PolynomialPoints<F>> {
+    let mut terms = terms;
+    let (first, coefficient) = terms.next().ok_or_else(|| StarkToolsError::InvalidDegree("no polynomial to fold in this round".to_string()))?;
+    let mut combined = first.clone();
+    if coefficient != F::ONE {
+        combined.points.values_mut().for_each(|point| **point = Point::new(point.get_x(), point.get_y() * coefficient));
+    }
+    for (polynomial, coefficient) in terms {
+        for (x, point) in combined.points.iter_mut() {
+            let y = polynomial.points.get(x).ok_or_else(|| StarkToolsError::InvalidDomain("polynomials evaluated over different domains".to_string()))?.get_y();
+            **point = Point::new(*x, point.get_y() + y * coefficient);
+        }
+    }
+    Ok(combined)
+}
+
+/// `1, x, x^2, ..., x^(n-1)`.
+fn powers<F: PrimeField>(x: F, n: usize) -> Vec<F> {
+    std::iter::successors(Some(F::ONE), |power| Some(*power * x)).take(n).collect()
+}
+
+/// Combines the coset values opened in each commitment of a layer.
+fn combine_values<F: PrimeField>(layer_values: &[Vec<F>], coefficients: &[F]) -> Vec<F> {
+    let mut combined = vec![F::ZERO; layer_values.first().map_or(0, |values| values.len())];
+    for (values, coefficient) in layer_values.iter().zip(coefficients) {
+        for (c, v) in combined.iter_mut().zip(values) {
+            *c += *coefficient * v;
+        }
+    }
+    combined
+}
+
+impl<'b, H, G, P, F, Raw> 
+ProximityProofProver<'b, H, G, P, F, Raw> for FRIProtocol<F, G, H> where 
     F: PrimeField,
     G: CurveGroup,
     H: DuplexSpongeInterface,
@@ -101,58 +1049,18 @@ ProximityProofProver<'b, H, G, P, F, Raw> for FRIProtocol<G, H, D, S, SBIGINT> w
         polynomial: &Commited<P>,
         // commitment: &Commitment,
     ) -> spongefish::ProofResult<&'b [u8]> {
-        let mut polynomial = Commited::new(polynomial.ptree.clone(), polynomial.data.clone().fft(4));
-        // polynomial.data = polynomial.data.fft(1);
-        let fold_num = (polynomial.data.degree as f32 + 1.).log2().ceil() as i32;
-        println!("Number of folds: {fold_num}, D: {D}");
-        // assert!(fold_num == D as i32);
-        for i in 0..D {
-            let folding_bytes = prover_state.challenge_bytes::<S>().unwrap();
-            let folding_randomness: BigInt<SBIGINT> = bytes_to_bigints(folding_bytes.into());
-            // println!("before {:?} after {:?}", folding_bytes, folding);
-            let fold = polynomial.data.fold_bigint(self.rate, folding_randomness).commit(&self.pedersen_config);
-            let commitment: Root = fold.ptree.root();
-            prover_state.add_bytes(&(commitment.to_sponge_bytes_as_vec())).unwrap();
-            
-            println!("Making {} queries", self.queries[i]);
-            for _ in 0..self.queries[i] {
-                let query_bytes = prover_state.challenge_bytes::<2>().unwrap();
-                let max_index = (1 << ((D - i) as u64)) * self.rate;
-
-                let leaf0_index: u64 = (query_bytes[0] as u64 * 256 + query_bytes[1] as u64) % max_index;
-                let leaf1_index = (leaf0_index + (polynomial.data.degree + 1) * self.rate / 2) % ((polynomial.data.degree + 1) * self.rate);
-                let fold_leaf_index = ((leaf0_index * 2 ) % max_index) / 2;
-                write_merkleproofs(&polynomial, leaf0_index, prover_state)?;
-                write_merkleproofs(&polynomial, leaf1_index, prover_state)?;
-                write_merkleproofs(&fold, fold_leaf_index, prover_state)?;
-            }
-            polynomial = fold;
-        }
-        Ok(prover_state.narg_string())
+        let polynomial = Commited {
+            data: polynomial.data.clone().fft(self.config.blowup)?,
+            ptree: polynomial.ptree.clone(),
+            ordering: polynomial.ordering
+        };
+        Ok(self.prove_batch(prover_state, &[polynomial])?)
     }
 }
 
-fn write_merkleproofs<H: DuplexSpongeInterface, F: PrimeField>(
-    polynomial: &Commited<PolynomialPoints<F>>,
-    leaf_index: u64,
-    prover_state: &mut spongefish::ProverState<H>,
-) -> Result<(), ProofError> {
-
-    let (leaf_val, path) = prove_leaf_index(polynomial, leaf_index).unwrap();
-    let leaf_arr: Vec<u8> = leaf_val.into_bigint().to_bytes_be().try_into().map_err(|_| {
-        ProofError::SerializationError
-    })?;
-    prover_state.add_bytes(&leaf_arr).unwrap();
-    let proof = path_to_bytes(path.clone()).unwrap();
-    proof.iter().for_each(|p|  {
-        prover_state.add_bytes(p).unwrap()
-    });
-    Ok(())
-}
-
-impl<'b, H, G, const D: usize, const S: usize, const SBIGINT: usize> 
-ProximityProofVerifier<'b, H, G, Root> for FRIProtocol<G, H, D, S, SBIGINT> where 
-    // T: MontConfig<N>,
+impl<'b, F, H, G> 
+ProximityProofVerifier<'b, H, G, F, MerkleCap> for FRIProtocol<F, G, H> where 
+    F: PrimeField,
     H: DuplexSpongeInterface,
     G: CurveGroup,
     // Commitment: Absorb + std::fmt::Display,
@@ -162,172 +1070,74 @@ ProximityProofVerifier<'b, H, G, Root> for FRIProtocol<G, H, D, S, SBIGINT> wher
         + FieldToUnitDeserialize<G::ScalarField>
         + UnitToField<G::ScalarField> {
 
-    fn verify<F: PrimeField>(
+    fn verify(
         &self,
         verifier_state: &mut spongefish::VerifierState<H>,
         // the commitment to the polynomial
-        commitment: &'b Root,
+        commitment: &'b MerkleCap,
     ) -> spongefish::ProofResult<()> {
-
-        let roots_length = (1 << (D as u64)) * self.rate;
-        let mut roots = HashMap::<u64, F>::new();
-        let omega = F::get_root_of_unity(roots_length).unwrap();
-        let mut root = F::ONE;
-        for i in 0..roots_length {
-            roots.insert(i, root);
-            root *= omega;
-        }
-
-        let mut fold_leaf_value = F::ZERO;
-        let mut fold_commitment = [0; 32];
-
-        let mut commitment: Root = commitment.clone();
-        for i in 0..D {
-            let max_index = (1 << ((D - i) as u64)) * self.rate;
-            let fold_r_bytes = verifier_state.challenge_bytes::<S>()?;
-            let fold_randomness = F::from_be_bytes_mod_order(&fold_r_bytes);
-            fold_commitment = verifier_state.next_bytes::<32>()?;
-            fold_commitment.reverse();
-
-            for _ in 0..self.queries[i] {
-                let query_bytes = verifier_state.challenge_bytes::<2>()?;
-                let leaf0_index: u64 = (query_bytes[0] as u64 * 256 + query_bytes[1] as u64) % max_index;
-                let leaf1_index = (leaf0_index + max_index / 2) % max_index;
-                let fold_leaf_index = ((leaf0_index * 2 ) % max_index) / 2;
-
-                let leaf0_value_bytes = verifier_state.next_bytes::<S>()?;
-                let leaf0_value = F::from_be_bytes_mod_order(&leaf0_value_bytes);
-                read_and_verify_merkle(
-                    leaf0_value, 
-                    D - i + (self.rate as f64).log2().ceil() as usize, 
-                    leaf0_index as usize, 
-                    commitment, 
-                    &self.pedersen_config, 
-                    verifier_state
-                )?;
-
-
-                let leaf1_value_bytes = verifier_state.next_bytes::<S>().map_err(|_| ProofError::SerializationError)?;
-                let leaf1_value = F::from_be_bytes_mod_order(&leaf1_value_bytes);
-                read_and_verify_merkle(
-                    leaf1_value, 
-                    D - i + (self.rate as f64).log2().ceil() as usize, 
-                    leaf1_index as usize, 
-                    commitment, 
-                    &self.pedersen_config, 
-                    verifier_state
-                )?;
-
-
-                let fold_leaf_value_bytes = verifier_state.next_bytes::<S>().map_err(|_| ProofError::SerializationError)?;
-                fold_leaf_value= F::from_be_bytes_mod_order(&fold_leaf_value_bytes);
-                read_and_verify_merkle(
-                    fold_leaf_value, 
-                    D - i - 1 + (self.rate as f64).log2().ceil() as usize, 
-                    fold_leaf_index as usize, 
-                    bytes_to_bls(fold_commitment), 
-                    &self.pedersen_config, 
-                    verifier_state
-                )?;
-
-                let leaf0_x_value = roots.get_key_value(&((leaf0_index * (1 << i)) % roots_length)).unwrap().1;
-                if fold_leaf_value != ((leaf0_value * (*leaf0_x_value + fold_randomness) + leaf1_value * (*leaf0_x_value - fold_randomness)) / leaf0_x_value.double()) {
-                    return Err(ProofError::InvalidProof);
-                }
-            }
-            commitment = bytes_to_bls(fold_commitment);
-        }
-        let mut final_polynomial_commitment = PolynomialCoefficient::<F>::new(
-            0, vec![fold_leaf_value])
-            .fft(self.rate)
-            .commit(&self.pedersen_config)
-            .ptree
-            .root()
-            .to_sponge_bytes_as_vec();
-        final_polynomial_commitment.reverse();
-        if final_polynomial_commitment != fold_commitment {
-            return Err(ProofError::InvalidProof);
-        }
-        Ok(())
+        Ok(self.verify_batch(verifier_state, std::slice::from_ref(commitment), &[self.config.degree_bound])?)
     }
 }
 
+impl<F, G, H> PolynomialCommitmentScheme<F, H> for FRIProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>
+{
+    type Parameters = FriConfig;
+    type Commitment = MerkleCap;
+    type CommitterState = Commited<PolynomialPoints<F>>;
 
-
-fn read_and_verify_merkle<H: DuplexSpongeInterface, F: PrimeField>(
-    leaf_value: F,
-    path_length: usize,
-    leaf_index: usize,
-    commitment: Root,
-    pedersen_config: &PedersenTreeConfig, 
-    verifier_state: &mut spongefish::VerifierState<H>
-) -> Result<(), ProofError> {
-
-    let mut proof: Vec<[u8; 32]> = vec![];
-    for _ in 0..path_length {
-        proof.push(verifier_state.next_bytes::<32>().map_err(|_| ProofError::SerializationError)?);
-    }
-    let path = bytes_to_path(proof.clone(), leaf_index as usize).map_err(|_| ProofError::InvalidProof).unwrap();
-    let verification_result = pedersen_config.verify_path(path.clone(), commitment, leaf_value).map_err(|x| ProofError::InvalidProof)?;
-    if !verification_result {
-        return Err(ProofError::InvalidProof);
+    /// Samples fresh Pedersen parameters; commitments use `LeafOrdering::Natural` unless set with `with_leaf_ordering`.
+    fn setup<R: Rng>(rng: &mut R, parameters: FriConfig) -> StarkToolsResult<Self> {
+        Self::new(parameters, PedersenTreeConfig::new(rng))
     }
-    return Ok(());
-}
-
-
-
-pub fn fri_test(pedersen_config: &PedersenTreeConfig) {
-    // Instantiate the group and the random oracle:
-    // Set the group:
-    type G = ark_ed_on_bls12_381::EdwardsProjective;
-    // type G = Projective;
-    // Set the hash function (commented out other valid choices):
-    // type H = spongefish::hash::Keccak;
-    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
-    // type H = spongefish::hash::legacy::DigestBridge<sha2::Sha256>;
-
-    /*
-        Couldn't find any better way to encode the following :(
-    */
-    const RATE: u64 = 8;
-    const POLYNOMIAL_DEGREE: u64 = 7;
-    const POLYNOMIAL_DEGREE_LOG: usize = 3;
-    const QUERIES: [u32; POLYNOMIAL_DEGREE_LOG] = [3; POLYNOMIAL_DEGREE_LOG];
-    const S: usize = (Field::MODULUS_BIT_SIZE/8) as usize;
-    const SBIGINT: usize = S/8;
-
-
-    let fri = FRIProtocol::<G, H, POLYNOMIAL_DEGREE_LOG, S, SBIGINT>::new(
-        QUERIES,
-        RATE,
-        pedersen_config.clone()
-    );
-    let io: DomainSeparator<H> = fri.new_pp_proof();
-
-    let mut rnd = ark_std::test_rng();
-
-    let mut prover_state = io.to_prover_state();
-    let commited_poly = 
-        PolynomialCoefficient::<Field>::random_poly(&mut rnd, POLYNOMIAL_DEGREE).fft(RATE)
-        .commit(pedersen_config);
 
+    /// Commits to the evaluations of `polynomial`, of degree below `degree_bound`, over the whole FRI domain.
+    fn commit(&self, polynomial: PolynomialCoefficient<F>) -> StarkToolsResult<(MerkleCap, Commited<PolynomialPoints<F>>)> {
+        if polynomial.degree >= self.config.degree_bound {
+            return Err(StarkToolsError::InvalidDegree(format!("polynomial of degree {} is not below the degree bound {}", polynomial.degree, self.config.degree_bound)));
+        }
+        // pad to the degree bound so that the evaluations cover the domain FRI folds
+        let mut coefficients = polynomial.coefficients;
+        coefficients.resize(polynomial.degree as usize + 1, F::ZERO);
+        coefficients.resize(self.config.degree_bound as usize, F::ZERO);
+        let padded = PolynomialCoefficient { degree: self.config.degree_bound - 1, coefficients };
+        let commited = padded.fft(self.config.blowup)?.commit_with_ordering(&self.pedersen_config, self.leaf_ordering)?;
+        Ok((commited.ptree.cap(), commited))
+    }
 
-    prover_state.public_bytes(&commited_poly.ptree.root().to_sponge_bytes_as_vec()).unwrap();
-    prover_state.ratchet().unwrap();
+    fn add_commitment(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
+        ds.add_bytes(DIGEST_BYTES * self.cap_size(self.config.domain_size(0)), "public commitment (C)")
+    }
 
-    let proof = fri.prove(
-        &mut prover_state, 
-        &commited_poly,
-    ).expect("FRI proof generation faild!");
+    fn commitment_to_bytes(&self, commitment: &MerkleCap) -> Vec<u8> {
+        cap_to_bytes(commitment)
+    }
 
-    // Print out the hex-encoded FRI proof.
-    println!("FRI Proof:\n{}", hex::encode(proof));
+    fn add_opening(&self, ds: DomainSeparator<H>, num_points: usize) -> DomainSeparator<H> {
+        self.add_openings_domsep(ds, num_points)
+    }
 
-    // Verify the proof: create the verifier transcript, add the statement to it, and invoke the verifier.
-    let mut verifier_state = io.to_verifier_state(proof);
-    verifier_state.public_bytes(&commited_poly.ptree.root().to_sponge_bytes_as_vec()).unwrap();
-    verifier_state.ratchet().unwrap();
+    fn open(
+        &self,
+        prover_state: &mut ProverState<H>,
+        committer_state: &Commited<PolynomialPoints<F>>,
+        points: &[F]
+    ) -> StarkToolsResult<Vec<F>> {
+        self.prove_openings(prover_state, committer_state, points)
+    }
 
-    fri.verify::<Field>(&mut verifier_state, &commited_poly.ptree.root()).and_then(|_| {println!("FRI proof successfully verified!"); Ok(())}).expect("Invalid proof");
-}
\ No newline at end of file
+    /// Checks that the polynomial committed to by `commitment` has degree below `degree_bound` and takes `values` at `points`.
+    fn verify_opening(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &MerkleCap,
+        points: &[F],
+        values: &[F]
+    ) -> StarkToolsResult<()> {
+        self.verify_openings(verifier_state, commitment, points, values)
+    }
+}
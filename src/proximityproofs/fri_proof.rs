@@ -0,0 +1,310 @@
+use ark_ff::{BigInteger, PrimeField};
+use serde_json::{json, Value};
+use crate::{error::{StarkToolsError, StarkToolsResult}, merkletree::{MerkleCap, Root}};
+use crate::proximityproofs::utils::{bytes_to_bls, bytes_to_cap, cap_to_bytes, digests_to_bytes};
+
+const FRI_PROOF_MAGIC: &[u8; 4] = b"STFP";
+/// Version of both the binary and the JSON encoding, bumped whenever either changes.
+pub const FRI_PROOF_VERSION: u32 = 1;
+
+/// The values of a coset that folds into one point, with their Merkle paths.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CosetOpening<F> {
+    /// Values in coset order, see `CosetOpener::coset`.
+    pub values: Vec<F>,
+    /// With `LeafOrdering::Natural` one path per value, leaf sibling first;
+    /// with `LeafOrdering::BitReversed` the single path above the block of leaves.
+    pub paths: Vec<Vec<Root>>,
+}
+
+/// A leaf of a committed fold with its Merkle path, leaf sibling first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LeafOpening<F> {
+    pub value: F,
+    pub path: Vec<Root>,
+}
+
+/// What the prover answers to one query of a round.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FriQuery<F> {
+    /// The queried coset of the codeword folded in the round.
+    pub coset: CosetOpening<F>,
+    /// The point the coset folds into, opened in the next commitment.
+    /// Only sent with `QueryMode::PerRound` and in every round but the last.
+    pub fold_leaf: Option<LeafOpening<F>>,
+}
+
+/// The messages of a FRI proof for one committed codeword, as opposed to the NARG string they are written to.
+/// `FRIProtocol::parse_proof` reads one out of a NARG string and `FRIProtocol::proof_to_narg` writes it back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FriProof<F> {
+    /// The cap of the fold committed in every round but the last.
+    pub fold_commitments: Vec<MerkleCap>,
+    /// Coefficients of the remainder polynomial sent in the last round.
+    pub final_polynomial: Vec<F>,
+    /// Proof of work nonces in transcript order: one per round with `QueryMode::PerRound`,
+    /// a single one with `QueryMode::Propagated`, none without proof of work.
+    pub pow_nonces: Vec<u64>,
+    /// The queries checked in each round.
+    pub queries: Vec<Vec<FriQuery<F>>>,
+}
+
+/// Reads the prover messages of a NARG string, or of a binary encoded proof, front to back.
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn take(&mut self, length: usize) -> StarkToolsResult<&'a [u8]> {
+        if self.bytes.len() < length {
+            return Err(malformed(format!("expected {length} more bytes, {} left", self.bytes.len())));
+        }
+        let (head, tail) = self.bytes.split_at(length);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    /// A field element in the big-endian form the transcript holds it in.
+    pub fn field_element<F: PrimeField>(&mut self) -> StarkToolsResult<F> {
+        Ok(F::from_be_bytes_mod_order(self.take(field_bytes::<F>())?))
+    }
+
+    /// Digests in the form `digests_to_bytes` writes them.
+    pub fn digests(&mut self, count: usize) -> StarkToolsResult<Vec<Root>> {
+        (0..count).map(|_| bytes_to_bls(self.take(32)?.try_into().unwrap())).collect()
+    }
+
+    /// A cap of `size` digests in the form `cap_to_bytes` writes it.
+    pub fn cap(&mut self, size: usize) -> StarkToolsResult<MerkleCap> {
+        bytes_to_cap(self.take(32 * size)?)
+    }
+
+    pub fn u64_be(&mut self) -> StarkToolsResult<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn u64_le(&mut self) -> StarkToolsResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Fails if anything is left to read.
+    pub fn finish(self) -> StarkToolsResult<()> {
+        if !self.bytes.is_empty() {
+            return Err(malformed(format!("{} trailing bytes", self.bytes.len())));
+        }
+        Ok(())
+    }
+}
+
+/// Shorthand for `StarkToolsError::MalformedProof`.
+pub fn malformed(message: impl Into<String>) -> StarkToolsError {
+    StarkToolsError::MalformedProof(message.into())
+}
+
+/// Number of bytes a field element takes in the transcript.
+fn field_bytes<F: PrimeField>() -> usize {
+    F::BigInt::NUM_LIMBS * 8
+}
+
+fn digest_bytes(digests: &[Root]) -> Vec<u8> {
+    digests_to_bytes(digests).expect("digests serialise to 32 bytes").concat()
+}
+
+fn write_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_field_elements<F: PrimeField>(bytes: &mut Vec<u8>, values: &[F]) {
+    write_u64(bytes, values.len() as u64);
+    for value in values {
+        bytes.extend(value.into_bigint().to_bytes_be());
+    }
+}
+
+fn write_digests(bytes: &mut Vec<u8>, digests: &[Root]) {
+    write_u64(bytes, digests.len() as u64);
+    bytes.extend(digest_bytes(digests));
+}
+
+fn read_field_elements<F: PrimeField>(reader: &mut ByteReader) -> StarkToolsResult<Vec<F>> {
+    let count = reader.u64_le()?;
+    (0..count).map(|_| reader.field_element()).collect()
+}
+
+fn read_digests(reader: &mut ByteReader) -> StarkToolsResult<Vec<Root>> {
+    let count = reader.u64_le()?;
+    (0..count).map(|_| Ok(reader.digests(1)?[0])).collect()
+}
+
+fn field_to_hex<F: PrimeField>(value: &F) -> Value {
+    Value::String(hex::encode(value.into_bigint().to_bytes_be()))
+}
+
+fn digests_to_hex(digests: &[Root]) -> Value {
+    Value::Array(digests_to_bytes(digests).expect("digests serialise to 32 bytes").iter().map(|d| Value::String(hex::encode(d))).collect())
+}
+
+fn json_array<'a>(value: &'a Value, name: &str) -> StarkToolsResult<&'a Vec<Value>> {
+    value.get(name).and_then(Value::as_array).ok_or_else(|| malformed(format!("missing array `{name}`")))
+}
+
+fn hex_bytes(value: &Value, length: usize) -> StarkToolsResult<Vec<u8>> {
+    let bytes = value.as_str().and_then(|s| hex::decode(s).ok()).ok_or_else(|| malformed(format!("expected a hex string, got {value}")))?;
+    if bytes.len() != length {
+        return Err(malformed(format!("expected {length} hex encoded bytes, got {}", bytes.len())));
+    }
+    Ok(bytes)
+}
+
+fn hex_to_field<F: PrimeField>(value: &Value) -> StarkToolsResult<F> {
+    Ok(F::from_be_bytes_mod_order(&hex_bytes(value, field_bytes::<F>())?))
+}
+
+fn hex_to_digests(value: &Value) -> StarkToolsResult<Vec<Root>> {
+    value.as_array().ok_or_else(|| malformed(format!("expected an array of digests, got {value}")))?
+        .iter()
+        .map(|digest| bytes_to_bls(hex_bytes(digest, 32)?.try_into().unwrap()))
+        .collect()
+}
+
+impl<F: PrimeField> FriProof<F> {
+    /// Binary encoding: the magic `STFP`, the version, then every list as a little-endian `u64` length
+    /// followed by its items. Field elements and digests keep the byte order of the transcript.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = FRI_PROOF_MAGIC.to_vec();
+        bytes.extend_from_slice(&FRI_PROOF_VERSION.to_le_bytes());
+        write_u64(&mut bytes, field_bytes::<F>() as u64);
+        write_u64(&mut bytes, self.fold_commitments.len() as u64);
+        for cap in &self.fold_commitments {
+            write_u64(&mut bytes, cap.len() as u64);
+            bytes.extend(cap_to_bytes(cap));
+        }
+        write_field_elements(&mut bytes, &self.final_polynomial);
+        write_u64(&mut bytes, self.pow_nonces.len() as u64);
+        for nonce in &self.pow_nonces {
+            write_u64(&mut bytes, *nonce);
+        }
+        write_u64(&mut bytes, self.queries.len() as u64);
+        for round in &self.queries {
+            write_u64(&mut bytes, round.len() as u64);
+            for query in round {
+                write_field_elements(&mut bytes, &query.coset.values);
+                write_u64(&mut bytes, query.coset.paths.len() as u64);
+                for path in &query.coset.paths {
+                    write_digests(&mut bytes, path);
+                }
+                match &query.fold_leaf {
+                    None => bytes.push(0),
+                    Some(leaf) => {
+                        bytes.push(1);
+                        bytes.extend(leaf.value.into_bigint().to_bytes_be());
+                        write_digests(&mut bytes, &leaf.path);
+                    }
+                }
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> StarkToolsResult<Self> {
+        let mut reader = ByteReader::new(bytes);
+        if reader.take(4)? != FRI_PROOF_MAGIC {
+            return Err(malformed("not a FRI proof"));
+        }
+        let version = u32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+        if version != FRI_PROOF_VERSION {
+            return Err(malformed(format!("unsupported FRI proof version {version}, expected {FRI_PROOF_VERSION}")));
+        }
+        let encoded_field_bytes = reader.u64_le()?;
+        if encoded_field_bytes != field_bytes::<F>() as u64 {
+            return Err(malformed(format!("proof over {encoded_field_bytes} byte field elements, expected {}", field_bytes::<F>())));
+        }
+        let fold_commitments = (0..reader.u64_le()?)
+            .map(|_| {
+                let size = reader.u64_le()?;
+                (0..size).map(|_| Ok(reader.cap(1)?[0])).collect()
+            })
+            .collect::<StarkToolsResult<Vec<MerkleCap>>>()?;
+        let final_polynomial = read_field_elements(&mut reader)?;
+        let pow_nonces = (0..reader.u64_le()?).map(|_| reader.u64_le()).collect::<StarkToolsResult<Vec<u64>>>()?;
+        let num_rounds = reader.u64_le()?;
+        let mut queries = vec![];
+        for _ in 0..num_rounds {
+            let num_queries = reader.u64_le()?;
+            let mut round = vec![];
+            for _ in 0..num_queries {
+                let values = read_field_elements(&mut reader)?;
+                let paths = (0..reader.u64_le()?).map(|_| read_digests(&mut reader)).collect::<StarkToolsResult<Vec<Vec<Root>>>>()?;
+                let fold_leaf = match reader.take(1)?[0] {
+                    0 => None,
+                    1 => Some(LeafOpening { value: reader.field_element()?, path: read_digests(&mut reader)? }),
+                    flag => return Err(malformed(format!("invalid fold leaf flag {flag}"))),
+                };
+                round.push(FriQuery { coset: CosetOpening { values, paths }, fold_leaf });
+            }
+            queries.push(round);
+        }
+        reader.finish()?;
+        Ok(Self { fold_commitments, final_polynomial, pow_nonces, queries })
+    }
+
+    /// JSON encoding with the same content as `to_bytes`, field elements and digests as hex strings.
+    pub fn to_json(&self) -> String {
+        let queries: Vec<Value> = self.queries.iter().map(|round| {
+            Value::Array(round.iter().map(|query| json!({
+                "coset_values": query.coset.values.iter().map(field_to_hex).collect::<Vec<Value>>(),
+                "coset_paths": query.coset.paths.iter().map(|path| digests_to_hex(path)).collect::<Vec<Value>>(),
+                "fold_leaf": query.fold_leaf.as_ref().map(|leaf| json!({
+                    "value": field_to_hex(&leaf.value),
+                    "path": digests_to_hex(&leaf.path),
+                })),
+            })).collect())
+        }).collect();
+        json!({
+            "version": FRI_PROOF_VERSION,
+            "fold_commitments": self.fold_commitments.iter()
+                .map(|cap| Value::Array(cap_to_bytes(cap).chunks(32).map(|root| Value::String(hex::encode(root))).collect()))
+                .collect::<Vec<Value>>(),
+            "final_polynomial": self.final_polynomial.iter().map(field_to_hex).collect::<Vec<Value>>(),
+            "pow_nonces": self.pow_nonces,
+            "queries": queries,
+        }).to_string()
+    }
+
+    pub fn from_json(json: &str) -> StarkToolsResult<Self> {
+        let value: Value = serde_json::from_str(json).map_err(|e| malformed(format!("invalid JSON: {e}")))?;
+        let version = value.get("version").and_then(Value::as_u64).ok_or_else(|| malformed("missing `version`"))?;
+        if version != FRI_PROOF_VERSION as u64 {
+            return Err(malformed(format!("unsupported FRI proof version {version}, expected {FRI_PROOF_VERSION}")));
+        }
+        let fold_commitments = json_array(&value, "fold_commitments")?.iter()
+            .map(|cap| cap.as_array().ok_or_else(|| malformed(format!("expected an array of digests, got {cap}")))?
+                .iter()
+                .map(|root| Ok(bytes_to_cap(&hex_bytes(root, 32)?)?[0]))
+                .collect::<StarkToolsResult<MerkleCap>>())
+            .collect::<StarkToolsResult<Vec<MerkleCap>>>()?;
+        let final_polynomial = json_array(&value, "final_polynomial")?.iter().map(hex_to_field).collect::<StarkToolsResult<Vec<F>>>()?;
+        let pow_nonces = json_array(&value, "pow_nonces")?.iter()
+            .map(|nonce| nonce.as_u64().ok_or_else(|| malformed(format!("expected a nonce, got {nonce}"))))
+            .collect::<StarkToolsResult<Vec<u64>>>()?;
+        let queries = json_array(&value, "queries")?.iter().map(|round| {
+            round.as_array().ok_or_else(|| malformed(format!("expected an array of queries, got {round}")))?.iter().map(|query| {
+                let values = json_array(query, "coset_values")?.iter().map(hex_to_field).collect::<StarkToolsResult<Vec<F>>>()?;
+                let paths = json_array(query, "coset_paths")?.iter().map(hex_to_digests).collect::<StarkToolsResult<Vec<Vec<Root>>>>()?;
+                let fold_leaf = match query.get("fold_leaf") {
+                    None | Some(Value::Null) => None,
+                    Some(leaf) => Some(LeafOpening {
+                        value: hex_to_field(leaf.get("value").ok_or_else(|| malformed("missing fold leaf `value`"))?)?,
+                        path: hex_to_digests(leaf.get("path").ok_or_else(|| malformed("missing fold leaf `path`"))?)?,
+                    }),
+                };
+                Ok(FriQuery { coset: CosetOpening { values, paths }, fold_leaf })
+            }).collect::<StarkToolsResult<Vec<FriQuery<F>>>>()
+        }).collect::<StarkToolsResult<Vec<Vec<FriQuery<F>>>>>()?;
+        Ok(Self { fold_commitments, final_polynomial, pow_nonces, queries })
+    }
+}
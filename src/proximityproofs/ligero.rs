@@ -0,0 +1,298 @@
+use ark_ec::CurveGroup;
+use ark_ff::{BigInteger, PrimeField};
+use spongefish::{codecs::arkworks_algebra::{FieldDomainSeparator, FieldToUnitDeserialize, GroupDomainSeparator, GroupToUnitDeserialize, GroupToUnitSerialize, UnitToField}, ByteDomainSeparator, BytesToUnitDeserialize, BytesToUnitSerialize, DomainSeparator, DuplexSpongeInterface, ProofError, ProofResult, ProverState, UnitToBytes, VerifierState};
+use crate::{commitable::{Commited, LeafOrdering}, error::{StarkToolsError, StarkToolsResult}, merkletree::{new_pedersen_merkletree, MerkleCap, PedersenTreeConfig, Root}, polynomial::{Polynomial, PolynomialCoefficient}};
+use crate::proximityproofs::{cosets::read_field_element, narg_proximityproof::{ProximityProofDomainSeparator, ProximityProofProver, ProximityProofVerifier}, queries::{sample_queries, QUERY_SEED_BYTES}, utils::{bytes_to_bls, digests_to_bytes}};
+use std::marker::PhantomData;
+
+/// Parameters of a Ligero proximity test. The coefficients of the polynomial fill a matrix of `num_rows` rows,
+/// each row is Reed–Solomon encoded on its own, and the Merkle tree commits to the encoded matrix column by column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LigeroConfig {
+    /// The committed polynomial has degree below `degree_bound`.
+    pub degree_bound: u64,
+    /// Number of rows of the coefficient matrix, each holding `degree_bound / num_rows` coefficients.
+    pub num_rows: u64,
+    /// Inverse rate of the code every row is encoded with.
+    pub blowup: u64,
+    /// Number of columns of the encoded matrix the verifier opens.
+    pub queries: u32,
+}
+
+impl LigeroConfig {
+    pub fn new(degree_bound: u64, num_rows: u64, blowup: u64, queries: u32) -> StarkToolsResult<Self> {
+        if !degree_bound.is_power_of_two() {
+            return Err(StarkToolsError::InvalidDegree(format!("degree bound {degree_bound} is not a power of two")));
+        }
+        if num_rows < 2 || !num_rows.is_power_of_two() || num_rows > degree_bound {
+            return Err(StarkToolsError::InvalidConfig(format!("{num_rows} rows is not a power of two between 2 and the degree bound {degree_bound}")));
+        }
+        if blowup < 2 || !blowup.is_power_of_two() {
+            return Err(StarkToolsError::InvalidRate(format!("blowup {blowup} is not a power of two above one")));
+        }
+        if degree_bound.checked_mul(blowup).is_none() {
+            return Err(StarkToolsError::InvalidRate(format!("a matrix of {degree_bound} * {blowup} entries does not fit in a u64")));
+        }
+        let config = Self { degree_bound, num_rows, blowup, queries };
+        if queries == 0 || queries as u64 > config.num_columns() {
+            return Err(StarkToolsError::InvalidConfig(format!("{queries} queries out of {} columns", config.num_columns())));
+        }
+        Ok(config)
+    }
+
+    /// Number of coefficients in a row, the length of the message the prover sends.
+    pub fn row_length(&self) -> u64 {
+        self.degree_bound / self.num_rows
+    }
+
+    /// Number of columns of the encoded matrix, the length of each row's codeword.
+    pub fn num_columns(&self) -> u64 {
+        self.row_length() * self.blowup
+    }
+
+    /// Number of leaves of the committed tree, one per entry of the encoded matrix.
+    pub fn num_leaves(&self) -> u64 {
+        self.degree_bound * self.blowup
+    }
+}
+
+/// A Ligero-style proximity test: the verifier picks a random combination of the rows of the committed matrix,
+/// the prover sends the combined row as a message, and the verifier checks its encoding against randomly opened
+/// columns. The prover only encodes and hashes, there are no folding rounds, at the cost of proofs that grow with
+/// the square root of the degree.
+///
+/// The commitment is not the codeword of the polynomial but the encoded matrix from `commit`, whose column `j`
+/// is the block of leaves `j * num_rows..(j + 1) * num_rows`.
+pub struct LigeroProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField> {
+    pub config: LigeroConfig,
+    pub pedersen_config: PedersenTreeConfig,
+    marker_type1: PhantomData<H>,
+    marker_type2: PhantomData<G>,
+    marker_field: PhantomData<F>
+}
+
+impl<F, G, H> LigeroProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField> {
+
+    /// Fails when the field has no evaluation domain as large as a row's codeword.
+    pub fn new(config: LigeroConfig, pedersen_config: PedersenTreeConfig) -> StarkToolsResult<Self> {
+        if config.num_columns().trailing_zeros() > F::TWO_ADICITY {
+            return Err(StarkToolsError::MissingRootOfUnity { order: config.num_columns() });
+        }
+        Ok(Self {
+            config,
+            pedersen_config,
+            marker_type1: PhantomData,
+            marker_type2: PhantomData,
+            marker_field: PhantomData
+        })
+    }
+
+    fn field_bytes(&self) -> usize {
+        F::BigInt::NUM_LIMBS * 8
+    }
+
+    fn cap_size(&self) -> usize {
+        1 << self.pedersen_config.cap_height_for(self.config.num_leaves() as usize)
+    }
+
+    /// Number of digests in the path above a column.
+    fn column_path_length(&self) -> usize {
+        let num_leaves = self.config.num_leaves();
+        let path_length = num_leaves.trailing_zeros() as usize - self.pedersen_config.cap_height_for(num_leaves as usize);
+        path_length.saturating_sub(self.config.num_rows.trailing_zeros() as usize)
+    }
+
+    /// Column `index` is the evaluation of every row at `omega^index`.
+    fn column_point(&self, index: u64) -> F {
+        F::get_root_of_unity(self.config.num_columns()).unwrap().pow([index])
+    }
+
+    /// The rows of the coefficient matrix of `polynomial`, padded up to the degree bound.
+    fn rows(&self, polynomial: &PolynomialCoefficient<F>) -> Vec<Vec<F>> {
+        let mut coefficients = polynomial.coefficients.clone();
+        coefficients.resize(self.config.degree_bound as usize, F::ZERO);
+        coefficients.chunks(self.config.row_length() as usize).map(|row| row.to_vec()).collect()
+    }
+
+    /// The Reed–Solomon codeword of a row, in column order.
+    fn encode(&self, row: &[F]) -> StarkToolsResult<Vec<F>> {
+        let points = PolynomialCoefficient { degree: row.len() as u64 - 1, coefficients: row.to_vec() }.fft(self.config.blowup)?;
+        let roots = points.roots_preimage.as_ref().unwrap();
+        Ok((0..self.config.num_columns())
+            .map(|index| points.points[roots.get_by_right(&index).unwrap()].get_y())
+            .collect())
+    }
+
+    /// Encodes the rows of `polynomial` and commits to the resulting matrix column by column.
+    /// Fails if the degree of `polynomial` reaches the degree bound.
+    pub fn commit(&self, polynomial: PolynomialCoefficient<F>) -> StarkToolsResult<Commited<PolynomialCoefficient<F>>> {
+        if polynomial.coefficients.len() as u64 > self.config.degree_bound {
+            return Err(StarkToolsError::InvalidDegree(format!("{} coefficients do not fit the degree bound {}", polynomial.coefficients.len(), self.config.degree_bound)));
+        }
+        let codewords = self.rows(&polynomial).iter().map(|row| self.encode(row)).collect::<StarkToolsResult<Vec<Vec<F>>>>()?;
+        let leaves: Vec<F> = (0..self.config.num_columns() as usize)
+            .flat_map(|column| codewords.iter().map(move |codeword| codeword[column]))
+            .collect();
+        let ptree = new_pedersen_merkletree(&self.pedersen_config, leaves)?;
+        Ok(Commited { data: polynomial, ptree, ordering: LeafOrdering::Natural })
+    }
+
+    /// Random combination of the rows from a single challenge: row `i` gets `r^i`.
+    fn combination(&self, r: F) -> Vec<F> {
+        std::iter::successors(Some(F::ONE), |power| Some(*power * r)).take(self.config.num_rows as usize).collect()
+    }
+
+    /// Proves that `polynomial`, as returned by `commit`, is committed to as the encoding of a polynomial of degree below
+    /// `degree_bound`. The transcript must come from `new_pp_proof` with the commitment added as public bytes.
+    pub fn prove_commitment(&self, prover_state: &mut ProverState<H>, polynomial: &Commited<PolynomialCoefficient<F>>) -> StarkToolsResult<()> {
+        if polynomial.ptree.num_leaves() as u64 != self.config.num_leaves() {
+            return Err(StarkToolsError::InvalidConfig(format!("a tree of {} leaves does not commit to a matrix of {} entries", polynomial.ptree.num_leaves(), self.config.num_leaves())));
+        }
+        if polynomial.data.degree >= self.config.degree_bound {
+            return Err(StarkToolsError::InvalidDegree(format!("polynomial of degree {} is not below the degree bound {}", polynomial.data.degree, self.config.degree_bound)));
+        }
+        let rows = self.rows(&polynomial.data);
+        let mut bytes = vec![0u8; self.field_bytes()];
+        prover_state.fill_challenge_bytes(&mut bytes)?;
+        let combination = self.combination(F::from_be_bytes_mod_order(&bytes));
+        let mut combined_row = vec![F::ZERO; self.config.row_length() as usize];
+        for (row, coefficient) in rows.iter().zip(&combination) {
+            for (combined, value) in combined_row.iter_mut().zip(row) {
+                *combined += *coefficient * value;
+            }
+        }
+        for value in &combined_row {
+            prover_state.add_bytes(&value.into_bigint().to_bytes_be())?;
+        }
+
+        let query_seed = prover_state.challenge_bytes::<QUERY_SEED_BYTES>()?;
+        let queries = sample_queries(&query_seed, self.config.queries as usize, self.config.num_columns());
+        let codewords = rows.iter().map(|row| self.encode(row)).collect::<StarkToolsResult<Vec<Vec<F>>>>()?;
+        for column in queries {
+            for codeword in &codewords {
+                prover_state.add_bytes(&codeword[column as usize].into_bigint().to_bytes_be())?;
+            }
+            let auth_path = polynomial.ptree.generate_block_proof(column as usize, self.config.num_rows as usize)?;
+            for digest in digests_to_bytes(&auth_path).map_err(|_| ProofError::SerializationError)? {
+                prover_state.add_bytes(&digest)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<F, G, H> ProximityProofDomainSeparator<G, H> for LigeroProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>
+{
+    fn new_pp_proof(&self) -> DomainSeparator<H> {
+        let ds = DomainSeparator::new(&format!(
+            "Ligero proximity test, merkle cap height {}, {} rows",
+            self.pedersen_config.cap_height,
+            self.config.num_rows
+        ));
+        let ds = self.add_pp_statement(ds);
+        self.add_pp_domsep(ds)
+    }
+
+    fn add_pp_statement(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
+        ds.add_bytes(32 * self.cap_size(), "public commitment (C)")
+            .ratchet()
+    }
+
+    fn add_pp_domsep(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
+        let s = self.field_bytes();
+        let mut ds = ds
+            .challenge_bytes(s, "combination randomness")
+            .add_bytes(s * self.config.row_length() as usize, "combined row")
+            .challenge_bytes(QUERY_SEED_BYTES, "query seed");
+        for _ in 0..self.config.queries {
+            ds = ds.add_bytes(s * self.config.num_rows as usize, "column values");
+            for _ in 0..self.column_path_length() {
+                ds = ds.add_bytes(32, "column proof");
+            }
+        }
+        ds
+    }
+}
+
+impl<'b, H, G, P, F, Raw>
+ProximityProofProver<'b, H, G, P, F, Raw> for LigeroProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    P: Polynomial<F, Raw>,
+    ProverState<H>: GroupToUnitSerialize<G> + UnitToField<G::ScalarField>,
+    Self: ProximityProofDomainSeparator<G, H> {
+    /// `polynomial` must carry the tree built by `LigeroProtocol::commit`; `prove_commitment` takes what `commit` returns as is.
+    fn prove(
+        &self,
+        prover_state: &'b mut ProverState<H>,
+        polynomial: &Commited<P>,
+    ) -> ProofResult<&'b [u8]> {
+        let coefficients = polynomial.data.clone().fft(self.config.blowup)?.ifft(self.config.blowup)?;
+        let matrix = Commited { data: coefficients, ptree: polynomial.ptree.clone(), ordering: LeafOrdering::Natural };
+        self.prove_commitment(prover_state, &matrix)?;
+        Ok(prover_state.narg_string())
+    }
+}
+
+impl<'b, F, H, G>
+ProximityProofVerifier<'b, H, G, F, MerkleCap> for LigeroProtocol<F, G, H> where
+    F: PrimeField,
+    H: DuplexSpongeInterface,
+    G: CurveGroup,
+    ProverState<H>: GroupToUnitSerialize<G> + UnitToField<G::ScalarField>,
+    Self: ProximityProofDomainSeparator<G, H>,
+    for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<G>
+        + FieldToUnitDeserialize<G::ScalarField>
+        + UnitToField<G::ScalarField> {
+
+    fn verify(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &'b MerkleCap,
+    ) -> ProofResult<()> {
+        let mut bytes = vec![0u8; self.field_bytes()];
+        verifier_state.fill_challenge_bytes(&mut bytes)?;
+        let combination = self.combination(F::from_be_bytes_mod_order(&bytes));
+        let coefficients = (0..self.config.row_length())
+            .map(|_| read_field_element(self.field_bytes(), verifier_state))
+            .collect::<Result<Vec<F>, ProofError>>()?;
+        let combined_row = PolynomialCoefficient { degree: self.config.row_length() - 1, coefficients };
+
+        let query_seed = verifier_state.challenge_bytes::<QUERY_SEED_BYTES>()?;
+        let queries = sample_queries(&query_seed, self.config.queries as usize, self.config.num_columns());
+        for column in queries {
+            let values = (0..self.config.num_rows)
+                .map(|_| read_field_element(self.field_bytes(), verifier_state))
+                .collect::<Result<Vec<F>, ProofError>>()?;
+            let mut auth_path: Vec<Root> = vec![];
+            for _ in 0..self.column_path_length() {
+                auth_path.push(bytes_to_bls(verifier_state.next_bytes::<32>()?).map_err(|_| ProofError::InvalidProof)?);
+            }
+            let verification_result = self.pedersen_config
+                .verify_block(&values, column as usize, &auth_path, commitment, self.config.num_leaves() as usize)
+                .map_err(|_| ProofError::InvalidProof)?;
+            if !verification_result {
+                return Err(ProofError::InvalidProof);
+            }
+            // the combination of the column must be the encoded combined row at that column
+            let combined: F = values.iter().zip(&combination).map(|(value, coefficient)| *value * coefficient).sum();
+            if combined != combined_row.evaluate(self.column_point(column)) {
+                return Err(ProofError::InvalidProof);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,23 @@
-pub mod narg_proximityproof;
+pub(crate) mod narg_proximityproof;
 pub mod fri;
-pub mod utils;
\ No newline at end of file
+pub mod fri_proof;
+pub(crate) mod utils;
+pub(crate) mod pow;
+pub(crate) mod queries;
+pub mod pcs;
+pub(crate) mod cosets;
+pub mod stir;
+pub mod whir;
+pub mod ligero;
+pub(crate) mod transcript;
+
+pub use fri::{DeepOpening, FRIProtocol, FriConfig, QueryMode};
+pub use fri_proof::{FriProof, FRI_PROOF_VERSION};
+pub use ligero::{LigeroConfig, LigeroProtocol};
+pub use narg_proximityproof::{ProximityProofDomainSeparator, ProximityProofProver, ProximityProofVerifier};
+pub use pcs::PolynomialCommitmentScheme;
+pub use pow::MAX_POW_BITS;
+pub use stir::{STIRProtocol, StirConfig};
+pub use transcript::{narg_length, TranscriptOp};
+pub use utils::{bytes_to_cap, bytes_to_path, cap_to_bytes, path_to_bytes};
+pub use whir::{WHIRProtocol, WhirConfig};
@@ -1,7 +1,7 @@
 use ark_ff::PrimeField;
 use ark_ec::{CurveGroup};
-use stark_tools::commitable::Commited;
-use crate::Polynomial;
+use crate::commitable::Commited;
+use crate::polynomial::Polynomial;
 
 use spongefish::codecs::arkworks_algebra::{
     DomainSeparator, DuplexSpongeInterface,
@@ -25,15 +25,16 @@ pub trait ProximityProofProver<'b, H, G, P, F, Raw> where
         // commitment: &Commitment
     ) -> ProofResult<&'b [u8]>;
 }
-pub trait ProximityProofVerifier<'b, H, G, Commitment> where
+pub trait ProximityProofVerifier<'b, H, G, F, Commitment> where
     // T: MontConfig<N>,
+    F: PrimeField,
     H: DuplexSpongeInterface,
     G: CurveGroup,
     for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<G>
         + FieldToUnitDeserialize<G::ScalarField>
         + UnitToField<G::ScalarField> {
 
-    fn verify<F: PrimeField>(
+    fn verify(
         &self,
         verifier_state: &mut VerifierState<H>,
         // the commitment to the polynomial
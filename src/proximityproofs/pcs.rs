@@ -0,0 +1,44 @@
+use ark_ff::PrimeField;
+use ark_std::rand::Rng;
+use crate::{error::StarkToolsResult, polynomial::PolynomialCoefficient};
+
+use spongefish::{DomainSeparator, DuplexSpongeInterface, ProverState, VerifierState};
+
+/// Commits to a polynomial and later proves its evaluations at points chosen by the caller.
+/// Protocols written against this trait can swap the commitment scheme underneath.
+pub trait PolynomialCommitmentScheme<F: PrimeField, H: DuplexSpongeInterface>: Sized {
+    /// What `setup` needs besides randomness.
+    type Parameters;
+    type Commitment;
+    /// What the committer keeps to open its commitment.
+    type CommitterState;
+
+    fn setup<R: Rng>(rng: &mut R, parameters: Self::Parameters) -> StarkToolsResult<Self>;
+
+    fn commit(&self, polynomial: PolynomialCoefficient<F>) -> StarkToolsResult<(Self::Commitment, Self::CommitterState)>;
+
+    /// Add a commitment, absorbed as the public bytes `commitment_to_bytes`, to the domain separator.
+    fn add_commitment(&self, ds: DomainSeparator<H>) -> DomainSeparator<H>;
+
+    fn commitment_to_bytes(&self, commitment: &Self::Commitment) -> Vec<u8>;
+
+    /// Add an opening at `num_points` points of a commitment already in the transcript to the domain separator.
+    fn add_opening(&self, ds: DomainSeparator<H>, num_points: usize) -> DomainSeparator<H>;
+
+    /// Proves the evaluations of the committed polynomial at `points` and returns them.
+    fn open(
+        &self,
+        prover_state: &mut ProverState<H>,
+        committer_state: &Self::CommitterState,
+        points: &[F]
+    ) -> StarkToolsResult<Vec<F>>;
+
+    /// Checks that the polynomial committed to by `commitment` takes `values` at `points`.
+    fn verify_opening(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &Self::Commitment,
+        points: &[F],
+        values: &[F]
+    ) -> StarkToolsResult<()>;
+}
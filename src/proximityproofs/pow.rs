@@ -0,0 +1,60 @@
+use blake2::{Blake2s256, Digest};
+use spongefish::{BytesToUnitDeserialize, BytesToUnitSerialize, DuplexSpongeInterface, ProofError, ProverState, UnitToBytes, VerifierState};
+
+/// Bytes squeezed from the transcript as the grinding challenge.
+pub const POW_CHALLENGE_BYTES: usize = 32;
+/// Bytes of the nonce the prover writes to the transcript.
+pub const POW_NONCE_BYTES: usize = 8;
+/// Most bits of grinding a protocol accepts. Far below the 64 bits of the nonce, so `grind` finds one.
+pub const MAX_POW_BITS: u32 = 32;
+
+/// Number of leading zero bits of `blake2s(challenge || nonce)`.
+fn leading_zero_bits(challenge: &[u8], nonce: u64) -> u32 {
+    let digest = Blake2s256::new()
+        .chain_update(challenge)
+        .chain_update(nonce.to_be_bytes())
+        .finalize();
+    let mut zeros = 0;
+    for byte in digest {
+        zeros += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    zeros
+}
+
+/// Searches for the first nonce whose hash with `challenge` starts with `bits` zero bits.
+/// Expected work is `2^bits` hashes.
+pub fn grind(challenge: &[u8], bits: u32) -> u64 {
+    (0..=u64::MAX)
+        .find(|nonce| verify_pow(challenge, bits, *nonce))
+        .expect("no nonce satisfies the proof of work")
+}
+
+pub fn verify_pow(challenge: &[u8], bits: u32, nonce: u64) -> bool {
+    bits == 0 || leading_zero_bits(challenge, nonce) >= bits
+}
+
+/// Squeezes a challenge and writes a nonce solving the proof of work for it.
+pub fn write_pow<H: DuplexSpongeInterface>(
+    pow_bits: u32,
+    prover_state: &mut ProverState<H>,
+) -> Result<(), ProofError> {
+    let challenge = prover_state.challenge_bytes::<POW_CHALLENGE_BYTES>()?;
+    let nonce = grind(&challenge, pow_bits);
+    prover_state.add_bytes(&nonce.to_be_bytes())?;
+    Ok(())
+}
+
+pub fn read_and_verify_pow<H: DuplexSpongeInterface>(
+    pow_bits: u32,
+    verifier_state: &mut VerifierState<H>
+) -> Result<(), ProofError> {
+    let challenge = verifier_state.challenge_bytes::<POW_CHALLENGE_BYTES>()?;
+    let nonce = u64::from_be_bytes(verifier_state.next_bytes::<POW_NONCE_BYTES>()?);
+    if !verify_pow(&challenge, pow_bits, nonce) {
+        return Err(ProofError::InvalidProof);
+    }
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use blake2::{Blake2s256, Digest};
+
+/// Bytes squeezed from the transcript to seed the query sampler.
+pub const QUERY_SEED_BYTES: usize = 32;
+
+/// Expands `seed` into `count` distinct indices in `0..domain_size`, each uniform over the indices not drawn yet.
+/// Candidates are 64-bit words of `blake2s(seed || counter)` masked to the bit length of `domain_size`;
+/// those past the domain are rejected, and so are repeated indices.
+/// Panics if `count > domain_size`.
+pub fn sample_queries(seed: &[u8], count: usize, domain_size: u64) -> Vec<u64> {
+    assert!(count as u64 <= domain_size, "cannot sample {count} distinct indices out of {domain_size}");
+    let bits = u64::BITS - domain_size.saturating_sub(1).leading_zeros();
+    let mask = if bits == u64::BITS { u64::MAX } else { (1 << bits) - 1 };
+
+    let mut indices: Vec<u64> = Vec::with_capacity(count);
+    let mut counter: u64 = 0;
+    while indices.len() < count {
+        let block = Blake2s256::new()
+            .chain_update(seed)
+            .chain_update(counter.to_be_bytes())
+            .finalize();
+        counter += 1;
+        for word in block.chunks_exact(8) {
+            let candidate = u64::from_be_bytes(word.try_into().unwrap()) & mask;
+            if candidate < domain_size && !indices.contains(&candidate) && indices.len() < count {
+                indices.push(candidate);
+            }
+        }
+    }
+    indices
+}
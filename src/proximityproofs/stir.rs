@@ -0,0 +1,536 @@
+use ark_ec::CurveGroup;
+use ark_ff::{BigInteger, PrimeField};
+use bimap::BiHashMap;
+use spongefish::{codecs::arkworks_algebra::{FieldDomainSeparator, FieldToUnitDeserialize, GroupDomainSeparator, GroupToUnitDeserialize, GroupToUnitSerialize, UnitToField}, ByteDomainSeparator, BytesToUnitDeserialize, BytesToUnitSerialize, DomainSeparator, DuplexSpongeInterface, ProofError, ProofResult, ProverState, UnitToBytes, VerifierState};
+use crate::{point::Point, commitable::{Commitable, Commited, LeafOrdering}, error::{StarkToolsError, StarkToolsResult}, merkletree::{MerkleCap, PedersenTreeConfig}, polynomial::{Polynomial, PolynomialCoefficient, PolynomialPoints}};
+use crate::proximityproofs::{cosets::{fold_coset, read_field_element, CosetOpener}, narg_proximityproof::{ProximityProofDomainSeparator, ProximityProofProver, ProximityProofVerifier}, pow::{read_and_verify_pow, write_pow, MAX_POW_BITS, POW_CHALLENGE_BYTES, POW_NONCE_BYTES}, queries::{sample_queries, QUERY_SEED_BYTES}, utils::{bytes_to_cap, cap_to_bytes}};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Parameters of a STIR proof. Every round folds by `folding_factor` but only halves the evaluation domain,
+/// so the rate improves by `folding_factor / 2` each round and later rounds get by with fewer queries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StirConfig {
+    /// The committed polynomial has degree below `degree_bound`.
+    pub degree_bound: u64,
+    /// Inverse rate of the committed codeword, which has `blowup * degree_bound` evaluations.
+    pub blowup: u64,
+    /// Each round divides the degree bound by `folding_factor`.
+    pub folding_factor: u64,
+    /// Number of cosets of the function folded in round `i` that the verifier queries.
+    pub queries: Vec<u32>,
+    /// Points outside the domain at which every committed fold is evaluated.
+    pub ood_samples: u32,
+    /// The last round sends the coefficients of a fold of degree at most `final_degree` instead of committing to it.
+    pub final_degree: u64,
+    /// Leading zero bits of the proof of work ground before each round's queries, 0 to skip it.
+    pub pow_bits: u32,
+}
+
+impl StirConfig {
+    pub fn new(degree_bound: u64, blowup: u64, folding_factor: u64, queries: Vec<u32>, final_degree: u64) -> StarkToolsResult<Self> {
+        if !degree_bound.is_power_of_two() {
+            return Err(StarkToolsError::InvalidDegree(format!("degree bound {degree_bound} is not a power of two")));
+        }
+        if blowup < 2 || !blowup.is_power_of_two() {
+            return Err(StarkToolsError::InvalidRate(format!("blowup {blowup} is not a power of two above one")));
+        }
+        if folding_factor < 2 || !folding_factor.is_power_of_two() {
+            return Err(StarkToolsError::InvalidRate(format!("folding factor {folding_factor} is not a power of two above one")));
+        }
+        if degree_bound.checked_mul(blowup).is_none() {
+            return Err(StarkToolsError::InvalidRate(format!("a domain of {degree_bound} * {blowup} points does not fit in a u64")));
+        }
+        let remainder_bound = final_degree.checked_add(1).filter(|bound| bound.is_power_of_two() && *bound < degree_bound)
+            .ok_or_else(|| StarkToolsError::InvalidDegree(format!("final degree {final_degree} + 1 is not a power of two below the degree bound {degree_bound}")))?;
+        let fold_bits = degree_bound.trailing_zeros() - remainder_bound.trailing_zeros();
+        if !fold_bits.is_multiple_of(folding_factor.trailing_zeros()) {
+            return Err(StarkToolsError::InvalidConfig(format!("degree bound {degree_bound} does not fold down to {remainder_bound} by a factor of {folding_factor}")));
+        }
+        let num_rounds = (fold_bits / folding_factor.trailing_zeros()) as usize;
+        if queries.len() != num_rounds {
+            return Err(StarkToolsError::InvalidConfig(format!("expected query counts for {num_rounds} rounds, got {}", queries.len())));
+        }
+        if queries.contains(&0) {
+            return Err(StarkToolsError::InvalidConfig("every round needs at least one query".to_string()));
+        }
+        let config = Self { degree_bound, blowup, folding_factor, queries, final_degree, ood_samples: 1, pow_bits: 0 };
+        if let Some(round) = (0..num_rounds).find(|&i| config.queries[i] as u64 > config.domain_size(i) / folding_factor) {
+            return Err(StarkToolsError::InvalidConfig(format!("round {round} asks for {} queries but has only {} cosets", config.queries[round], config.domain_size(round) / folding_factor)));
+        }
+        Ok(config)
+    }
+
+    pub fn with_ood_samples(mut self, ood_samples: u32) -> Self {
+        self.ood_samples = ood_samples;
+        self
+    }
+
+    /// `STIRProtocol::new` rejects more than `MAX_POW_BITS`.
+    pub fn with_pow_bits(mut self, pow_bits: u32) -> Self {
+        self.pow_bits = pow_bits;
+        self
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Size of the evaluation domain of the function folded in `round`.
+    pub fn domain_size(&self, round: usize) -> u64 {
+        (self.degree_bound * self.blowup) >> round
+    }
+
+    /// Degree bound of the function folded in `round`.
+    pub fn degree(&self, round: usize) -> u64 {
+        self.degree_bound >> (round as u32 * self.folding_factor.trailing_zeros())
+    }
+}
+
+pub struct STIRProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField> {
+    pub config: StirConfig,
+    pub pedersen_config: PedersenTreeConfig,
+    pub leaf_ordering: LeafOrdering,
+    marker_type1: PhantomData<H>,
+    marker_type2: PhantomData<G>,
+    marker_field: PhantomData<F>
+}
+
+/// What the verifier learnt about a committed fold `g`: its values `values` at `points`.
+/// The next round folds the degree-corrected quotient `(g - answers) / vanishing(points) * sum_j (r * X)^j`,
+/// which has the same degree bound as `g` and is only a polynomial if `g` takes those values.
+struct FoldQuotient<F> {
+    points: Vec<F>,
+    values: Vec<F>,
+    combination_randomness: F,
+}
+
+impl<F: PrimeField> FoldQuotient<F> {
+    /// The next function at `x`, from the value `y` of the committed fold there.
+    fn evaluate(&self, x: F, y: F) -> Option<F> {
+        let mut vanishing = F::ONE;
+        let mut answer = F::ZERO;
+        for (j, (point, value)) in self.points.iter().zip(&self.values).enumerate() {
+            vanishing *= x - point;
+            let mut lagrange = *value;
+            for (l, other) in self.points.iter().enumerate() {
+                if l != j {
+                    lagrange *= (x - other) * (*point - other).inverse()?;
+                }
+            }
+            answer += lagrange;
+        }
+        let rx = self.combination_randomness * x;
+        let correction = (0..self.points.len()).fold((F::ONE, F::ONE), |(sum, power), _| (sum + power * rx, power * rx)).0;
+        Some((y - answer) * vanishing.inverse()? * correction)
+    }
+
+    /// The next function's coefficients, from the coefficients of the committed fold.
+    fn polynomial(&self, fold: &PolynomialCoefficient<F>) -> Option<PolynomialCoefficient<F>> {
+        // subtract the interpolant of the answers, then divide out each point
+        let mut numerator = fold.coefficients.clone();
+        for (j, (point, value)) in self.points.iter().zip(&self.values).enumerate() {
+            let others: Vec<F> = self.points.iter().enumerate().filter(|(l, _)| *l != j).map(|(_, other)| *other).collect();
+            let denominator: F = others.iter().map(|other| *point - other).product();
+            let scale = *value * denominator.inverse()?;
+            for (c, b) in numerator.iter_mut().zip(vanishing_polynomial(&others)) {
+                *c -= scale * b;
+            }
+        }
+        for point in &self.points {
+            numerator = divide_by_linear(&numerator, *point)?;
+        }
+        // times sum_j (r X)^j, of degree |points|
+        let correction: Vec<F> = std::iter::successors(Some(F::ONE), |power| Some(*power * self.combination_randomness))
+            .take(self.points.len() + 1)
+            .collect();
+        let mut coefficients = vec![F::ZERO; fold.coefficients.len()];
+        for (i, q) in numerator.iter().enumerate() {
+            for (j, c) in correction.iter().enumerate() {
+                if let Some(coefficient) = coefficients.get_mut(i + j) {
+                    *coefficient += *q * c;
+                }
+            }
+        }
+        Some(PolynomialCoefficient { degree: fold.degree, coefficients })
+    }
+}
+
+/// The domain folded in a round, `shift * generator^index` for every index below its size.
+struct RoundDomain<F> {
+    shift: F,
+    generator: F,
+}
+
+impl<F: PrimeField> RoundDomain<F> {
+    fn point(&self, index: u64) -> F {
+        self.shift * self.generator.pow([index])
+    }
+}
+
+/// Coefficients of `prod_j (X - points_j)`.
+fn vanishing_polynomial<F: PrimeField>(points: &[F]) -> Vec<F> {
+    let mut coefficients = vec![F::ONE];
+    for point in points {
+        coefficients.insert(0, F::ZERO);
+        for i in 0..coefficients.len() - 1 {
+            let next = coefficients[i + 1];
+            coefficients[i] -= *point * next;
+        }
+    }
+    coefficients
+}
+
+/// `coefficients / (X - point)`, `None` unless `point` is a root.
+fn divide_by_linear<F: PrimeField>(coefficients: &[F], point: F) -> Option<Vec<F>> {
+    let mut quotient = vec![F::ZERO; coefficients.len().saturating_sub(1)];
+    let mut carry = F::ZERO;
+    for i in (0..coefficients.len()).rev() {
+        carry = coefficients[i] + carry * point;
+        if i > 0 {
+            quotient[i - 1] = carry;
+        }
+    }
+    if carry.is_zero() { Some(quotient) } else { None }
+}
+
+/// Folds the coefficients by `folding_factor` with challenge `r`, matching `fold_coset`:
+/// coefficient `m` of the fold is `sum_t r^t * c[m * folding_factor + t]`.
+fn fold_coefficients<F: PrimeField>(polynomial: &PolynomialCoefficient<F>, folding_factor: u64, folding_randomness: F) -> PolynomialCoefficient<F> {
+    let coefficients: Vec<F> = polynomial.coefficients.chunks(folding_factor as usize)
+        .map(|chunk| chunk.iter().rev().fold(F::ZERO, |acc, c| acc * folding_randomness + c))
+        .collect();
+    PolynomialCoefficient { degree: coefficients.len() as u64 - 1, coefficients }
+}
+
+impl<F, G, H> STIRProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField> {
+
+    /// Fails when the field has no evaluation domain as large as the one `config` needs,
+    /// or when a round's out-of-domain and shift queries would leave the quotient without a degree.
+    pub fn new(config: StirConfig, pedersen_config: PedersenTreeConfig) -> StarkToolsResult<Self> {
+        if config.domain_size(0).trailing_zeros() > F::TWO_ADICITY {
+            return Err(StarkToolsError::MissingRootOfUnity { order: config.domain_size(0) });
+        }
+        if config.pow_bits > MAX_POW_BITS {
+            return Err(StarkToolsError::InvalidConfig(format!("{} bits of proof of work, at most {MAX_POW_BITS} are supported", config.pow_bits)));
+        }
+        let quotient_points = |i: usize| config.ood_samples as u64 + config.queries[i] as u64;
+        if let Some(round) = (0..config.num_rounds() - 1).find(|&i| quotient_points(i) >= config.degree(i + 1)) {
+            return Err(StarkToolsError::InvalidConfig(format!("round {round} constrains its fold at {} points but the fold has degree below {}", quotient_points(round), config.degree(round + 1))));
+        }
+        Ok(Self {
+            config,
+            pedersen_config,
+            leaf_ordering: LeafOrdering::Natural,
+            marker_type1: PhantomData,
+            marker_type2: PhantomData,
+            marker_field: PhantomData
+        })
+    }
+
+    /// With `LeafOrdering::BitReversed` every folding coset forms one block of leaves opened by a single Merkle path.
+    /// The committed input polynomial must use the same ordering.
+    pub fn with_leaf_ordering(mut self, leaf_ordering: LeafOrdering) -> Self {
+        self.leaf_ordering = leaf_ordering;
+        self
+    }
+
+    fn opener(&self) -> CosetOpener<'_, F> {
+        CosetOpener::new(&self.pedersen_config, self.leaf_ordering, self.config.folding_factor)
+    }
+
+    fn field_bytes(&self) -> usize {
+        self.opener().field_bytes()
+    }
+
+    fn squeeze_field_element(&self, prover_state: &mut ProverState<H>) -> ProofResult<F> {
+        let mut bytes = vec![0u8; self.field_bytes()];
+        prover_state.fill_challenge_bytes(&mut bytes)?;
+        Ok(F::from_be_bytes_mod_order(&bytes))
+    }
+
+    fn challenge_field_element(&self, verifier_state: &mut VerifierState<H>) -> ProofResult<F> {
+        let mut bytes = vec![0u8; self.field_bytes()];
+        verifier_state.fill_challenge_bytes(&mut bytes)?;
+        Ok(F::from_be_bytes_mod_order(&bytes))
+    }
+
+    /// The domain folded in `round`: the subgroup of `domain_size(round)` points, shifted by the product of the previous
+    /// rounds' generators. The committed codeword sits on the subgroup itself and each fold on `omega` times the squares of
+    /// the previous domain, which keeps every fold's domain apart from the previous domain's `folding_factor`-th powers,
+    /// where the shift queries lie.
+    fn domain(&self, round: usize) -> StarkToolsResult<RoundDomain<F>> {
+        let generator = |i: usize| {
+            let order = self.config.domain_size(i);
+            F::get_root_of_unity(order).ok_or(StarkToolsError::MissingRootOfUnity { order })
+        };
+        let shift = (0..round).map(generator).product::<StarkToolsResult<F>>()?;
+        Ok(RoundDomain { shift, generator: generator(round)? })
+    }
+
+    /// Evaluates `polynomial` on the domain folded in `round`, indexed like `RoundDomain::point`.
+    fn evaluate_on_domain(&self, polynomial: &PolynomialCoefficient<F>, round: usize) -> StarkToolsResult<PolynomialPoints<F>> {
+        let domain = self.domain(round)?;
+        let mut points = HashMap::new();
+        let mut roots_preimage = BiHashMap::new();
+        let mut x = domain.shift;
+        for index in 0..self.config.domain_size(round) {
+            points.insert(x, Box::new(Point::new(x, polynomial.evaluate(x))));
+            roots_preimage.insert(x, index);
+            x *= domain.generator;
+        }
+        Ok(PolynomialPoints { degree: self.config.degree(round) - 1, points, roots_preimage: Some(roots_preimage) })
+    }
+
+    fn add_query_sampling(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
+        let mut ds = ds;
+        if self.config.pow_bits > 0 {
+            ds = ds.challenge_bytes(POW_CHALLENGE_BYTES, "proof of work challenge");
+            ds = ds.add_bytes(POW_NONCE_BYTES, "proof of work nonce");
+        }
+        ds.challenge_bytes(QUERY_SEED_BYTES, "query seed")
+    }
+
+    /// Grinds the proof of work if any, then samples the cosets queried in `round`.
+    fn sample_queries_prover(&self, prover_state: &mut ProverState<H>, round: usize) -> Result<Vec<u64>, ProofError> {
+        if self.config.pow_bits > 0 {
+            write_pow(self.config.pow_bits, prover_state)?;
+        }
+        let query_seed = prover_state.challenge_bytes::<QUERY_SEED_BYTES>()?;
+        Ok(sample_queries(&query_seed, self.config.queries[round] as usize, self.config.domain_size(round) / self.config.folding_factor))
+    }
+
+    fn sample_queries_verifier(&self, verifier_state: &mut VerifierState<H>, round: usize) -> Result<Vec<u64>, ProofError> {
+        if self.config.pow_bits > 0 {
+            read_and_verify_pow(self.config.pow_bits, verifier_state)?;
+        }
+        let query_seed = verifier_state.challenge_bytes::<QUERY_SEED_BYTES>()?;
+        Ok(sample_queries(&query_seed, self.config.queries[round] as usize, self.config.domain_size(round) / self.config.folding_factor))
+    }
+
+    /// Proves that the committed codeword is close to a polynomial of degree below `degree_bound`.
+    /// The transcript must come from `new_pp_proof` with the commitment added as public bytes.
+    pub fn prove_codeword(&self, prover_state: &mut ProverState<H>, polynomial: &Commited<PolynomialPoints<F>>) -> StarkToolsResult<()> {
+        if polynomial.ordering != self.leaf_ordering {
+            return Err(StarkToolsError::InvalidConfig(format!("polynomial committed with {:?} leaves, expected {:?}", polynomial.ordering, self.leaf_ordering)));
+        }
+        let mut function = polynomial.data.clone().ifft(self.config.blowup)?;
+        function.coefficients.resize(self.config.degree_bound as usize, F::ZERO);
+
+        // folds[i] is committed in round `i` and queried through the next function in round `i + 1`
+        let mut folds: Vec<Commited<PolynomialPoints<F>>> = vec![];
+        for i in 0..self.config.num_rounds() {
+            let folding_randomness = self.squeeze_field_element(prover_state)?;
+            let fold = fold_coefficients(&function, self.config.folding_factor, folding_randomness);
+            let max_index = self.config.domain_size(i);
+            let domain = self.domain(i)?;
+            if i + 1 == self.config.num_rounds() {
+                for coefficient in &fold.coefficients {
+                    prover_state.add_bytes(&coefficient.into_bigint().to_bytes_be())?;
+                }
+                let queries = self.sample_queries_prover(prover_state, i)?;
+                let oracle = folds.last().unwrap_or(polynomial);
+                for query in queries {
+                    self.opener().write_coset_opening(oracle, query, max_index, prover_state)?;
+                }
+                break;
+            }
+
+            let commited = self.evaluate_on_domain(&fold, i + 1)?.commit_with_ordering(&self.pedersen_config, self.leaf_ordering)?;
+            prover_state.add_bytes(&cap_to_bytes(&commited.ptree.cap()))?;
+            let mut points = vec![];
+            for _ in 0..self.config.ood_samples {
+                points.push(self.squeeze_field_element(prover_state)?);
+            }
+            let mut values: Vec<F> = points.iter().map(|point| fold.evaluate(*point)).collect();
+            for value in &values {
+                prover_state.add_bytes(&value.into_bigint().to_bytes_be())?;
+            }
+            let queries = self.sample_queries_prover(prover_state, i)?;
+            let combination_randomness = self.squeeze_field_element(prover_state)?;
+            let oracle = folds.last().unwrap_or(polynomial);
+            for query in &queries {
+                self.opener().write_coset_opening(oracle, *query, max_index, prover_state)?;
+            }
+
+            // the shift queries are the folding_factor-th powers of the queried cosets
+            for query in &queries {
+                let point = domain.point(*query).pow([self.config.folding_factor]);
+                values.push(fold.evaluate(point));
+                points.push(point);
+            }
+            let quotient = FoldQuotient { points, values, combination_randomness };
+            function = quotient.polynomial(&fold)
+                .ok_or_else(|| StarkToolsError::InvalidDomain("an out-of-domain point coincides with a shift query".to_string()))?;
+            folds.push(commited);
+        }
+        Ok(())
+    }
+
+    /// Verifies a `prove_codeword` proof against `commitment`.
+    pub fn verify_codeword(&self, verifier_state: &mut VerifierState<H>, commitment: &MerkleCap) -> ProofResult<()> {
+        let mut oracle = commitment.clone();
+        // maps values of the last committed fold to the function folded in the current round
+        let mut quotient: Option<FoldQuotient<F>> = None;
+        for i in 0..self.config.num_rounds() {
+            let folding_randomness = self.challenge_field_element(verifier_state)?;
+            let max_index = self.config.domain_size(i);
+            let domain = self.domain(i)?;
+            let last_round = i + 1 == self.config.num_rounds();
+
+            let mut remainder = None;
+            let mut fold_commitment = None;
+            let mut points = vec![];
+            let mut values = vec![];
+            let mut combination_randomness = F::ZERO;
+            if last_round {
+                let coefficients = (0..=self.config.final_degree)
+                    .map(|_| read_field_element(self.field_bytes(), verifier_state))
+                    .collect::<Result<Vec<F>, ProofError>>()?;
+                remainder = Some(PolynomialCoefficient { degree: self.config.final_degree, coefficients });
+            } else {
+                let mut fold_commitment_bytes = vec![0; 32 * self.opener().cap_size(self.config.domain_size(i + 1))];
+                verifier_state.fill_next_bytes(&mut fold_commitment_bytes)?;
+                fold_commitment = Some(bytes_to_cap(&fold_commitment_bytes).map_err(|_| ProofError::InvalidProof)?);
+                for _ in 0..self.config.ood_samples {
+                    points.push(self.challenge_field_element(verifier_state)?);
+                }
+                for _ in 0..self.config.ood_samples {
+                    values.push(read_field_element(self.field_bytes(), verifier_state)?);
+                }
+            }
+            let queries = self.sample_queries_verifier(verifier_state, i)?;
+            if !last_round {
+                combination_randomness = self.challenge_field_element(verifier_state)?;
+            }
+
+            for query in &queries {
+                let coset = self.opener().coset(*query, max_index);
+                let mut coset_values = self.opener().read_coset_opening(verifier_state, &oracle, *query, max_index)?;
+                if let Some(quotient) = &quotient {
+                    for (index, value) in coset.iter().zip(coset_values.iter_mut()) {
+                        *value = quotient.evaluate(domain.point(*index), *value).ok_or(ProofError::InvalidProof)?;
+                    }
+                }
+                let x = domain.point(*query);
+                let folded = fold_coset(&coset_values, x, folding_randomness);
+                let point = x.pow([self.config.folding_factor]);
+                match &remainder {
+                    Some(remainder) => {
+                        if folded != remainder.evaluate(point) {
+                            return Err(ProofError::InvalidProof);
+                        }
+                    }
+                    None => {
+                        points.push(point);
+                        values.push(folded);
+                    }
+                }
+            }
+            if let Some(fold_commitment) = fold_commitment {
+                quotient = Some(FoldQuotient { points, values, combination_randomness });
+                oracle = fold_commitment;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<F, G, H> ProximityProofDomainSeparator<G, H> for STIRProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>
+{
+    fn new_pp_proof(&self) -> DomainSeparator<H> {
+        let ds = DomainSeparator::new(&format!(
+            "STIR proximity proof, merkle cap height {}, {:?} leaves",
+            self.pedersen_config.cap_height,
+            self.leaf_ordering
+        ));
+        let ds = self.add_pp_statement(ds);
+        self.add_pp_domsep(ds)
+    }
+
+    fn add_pp_statement(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
+        ds.add_bytes(32 * self.opener().cap_size(self.config.domain_size(0)), "public commitment (C)")
+            .ratchet()
+    }
+
+    fn add_pp_domsep(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
+        let mut ds = ds;
+        let s = self.field_bytes();
+        for i in 0..self.config.num_rounds() {
+            ds = ds.challenge_bytes(s, "folding randomness");
+            let last_round = i + 1 == self.config.num_rounds();
+            if last_round {
+                ds = ds.add_bytes(s * (self.config.final_degree as usize + 1), "remainder polynomial coefficients");
+            } else {
+                ds = ds.add_bytes(32 * self.opener().cap_size(self.config.domain_size(i + 1)), "fold commitment");
+                for _ in 0..self.config.ood_samples {
+                    ds = ds.challenge_bytes(s, "out-of-domain point");
+                }
+                ds = ds.add_bytes(s * self.config.ood_samples as usize, "out-of-domain evaluations");
+            }
+            ds = self.add_query_sampling(ds);
+            if !last_round {
+                ds = ds.challenge_bytes(s, "combination randomness");
+            }
+            for _ in 0..self.config.queries[i] {
+                ds = self.opener().add_coset_opening(ds, self.config.domain_size(i));
+            }
+        }
+        ds
+    }
+}
+
+impl<'b, H, G, P, F, Raw>
+ProximityProofProver<'b, H, G, P, F, Raw> for STIRProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    P: Polynomial<F, Raw>,
+    ProverState<H>: GroupToUnitSerialize<G> + UnitToField<G::ScalarField>,
+    Self: ProximityProofDomainSeparator<G, H> {
+    fn prove(
+        &self,
+        prover_state: &'b mut ProverState<H>,
+        polynomial: &Commited<P>,
+    ) -> ProofResult<&'b [u8]> {
+        let polynomial = Commited {
+            data: polynomial.data.clone().fft(self.config.blowup)?,
+            ptree: polynomial.ptree.clone(),
+            ordering: polynomial.ordering
+        };
+        self.prove_codeword(prover_state, &polynomial)?;
+        Ok(prover_state.narg_string())
+    }
+}
+
+impl<'b, F, H, G>
+ProximityProofVerifier<'b, H, G, F, MerkleCap> for STIRProtocol<F, G, H> where
+    F: PrimeField,
+    H: DuplexSpongeInterface,
+    G: CurveGroup,
+    ProverState<H>: GroupToUnitSerialize<G> + UnitToField<G::ScalarField>,
+    Self: ProximityProofDomainSeparator<G, H>,
+    for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<G>
+        + FieldToUnitDeserialize<G::ScalarField>
+        + UnitToField<G::ScalarField> {
+
+    fn verify(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &'b MerkleCap,
+    ) -> ProofResult<()> {
+        self.verify_codeword(verifier_state, commitment)
+    }
+}
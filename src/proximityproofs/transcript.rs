@@ -0,0 +1,29 @@
+use spongefish::{ByteDomainSeparator, DomainSeparator, DuplexSpongeInterface};
+
+/// One step of a transcript, in the order prover and verifier take it. A protocol lists its steps once
+/// and derives both its domain separator and the length of its proofs from that list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TranscriptOp {
+    /// `bytes` the prover writes to the NARG string.
+    Message { bytes: usize, label: &'static str },
+    /// `bytes` of verifier randomness squeezed from the sponge.
+    Challenge { bytes: usize, label: &'static str },
+    /// `bytes` both sides absorb as part of the statement; the prover does not write them.
+    Public { bytes: usize, label: &'static str },
+}
+
+/// Extends `ds` with every step of `schema`.
+pub fn add_schema<H: DuplexSpongeInterface>(ds: DomainSeparator<H>, schema: &[TranscriptOp]) -> DomainSeparator<H> {
+    schema.iter().fold(ds, |ds, op| match *op {
+        TranscriptOp::Message { bytes, label } | TranscriptOp::Public { bytes, label } => ds.add_bytes(bytes, label),
+        TranscriptOp::Challenge { bytes, label } => ds.challenge_bytes(bytes, label),
+    })
+}
+
+/// Number of bytes a proof following `schema` takes.
+pub fn narg_length(schema: &[TranscriptOp]) -> usize {
+    schema.iter().map(|op| match *op {
+        TranscriptOp::Message { bytes, .. } => bytes,
+        TranscriptOp::Challenge { .. } | TranscriptOp::Public { .. } => 0,
+    }).sum()
+}
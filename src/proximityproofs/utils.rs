@@ -1,56 +1,62 @@
 use ark_crypto_primitives::{merkle_tree::Path, sponge::Absorb};
 use ark_ff::{BigInt, PrimeField};
-use stark_tools::{commitable::Commited, merkletree::MerkleConfig, polynomial::PolynomialPoints};
+use crate::{commitable::Commited, error::{StarkToolsError, StarkToolsResult}, merkletree::{MerkleCap, MerkleConfig, Root}, polynomial::PolynomialPoints};
 
-pub fn path_to_bytes(path: Path<MerkleConfig>) -> Result<Vec<[u8; 32]>, String> {
+/// Bytes a Merkle digest, a Jubjub base field element, takes in the transcript.
+pub const DIGEST_BYTES: usize = 32;
+
+pub fn path_to_bytes(path: Path<MerkleConfig>) -> StarkToolsResult<Vec<[u8; 32]>> {
     // println!("path to bytes: {:?}", path);
     let mut res: Vec<[u8; 32]> = vec![];
-    res.push(path.leaf_sibling_hash.to_sponge_bytes_as_vec().try_into().map_err(|v: Vec<u8>| format!("expected 32 bytes, got {}", v.len()))?);
+    res.push(path.leaf_sibling_hash.to_sponge_bytes_as_vec().try_into().map_err(digest_length_error)?);
     res[0].reverse();
     for i in 0..path.auth_path.len() {
-        res.push(path.auth_path[i].to_sponge_bytes_as_vec().try_into().map_err(|v: Vec<u8>| format!("expected 32 bytes, got {}", v.len()))?);
+        res.push(path.auth_path[i].to_sponge_bytes_as_vec().try_into().map_err(digest_length_error)?);
         res[i + 1].reverse();
     }
     Ok(res)
 }
 // path to bytes: Path { leaf_sibling_hash: 29019666737956306800262246500759730249651687828223212625784994980408929564802, auth_path: [4982699993606047466420604557841274890212484493052668191907527988462817840057, 30034249863412613020425381884716586586712030053712760496840581412335049614427], leaf_index: 1 }
 // Path:       Ok(Path { leaf_sibling_hash: 5900475631263479918354771766276089294664006478654606367786112, auth_path: [6226884921503587318036801755921584717323419914465477444764292, 6182945209355880552831848976965162391676867545781016050271157], leaf_index: 1 })
-pub fn bytes_to_path(proof: Vec<[u8; 32]>, query_index: usize) -> Result<Path<MerkleConfig>, String> {
+pub fn bytes_to_path(proof: Vec<[u8; 32]>, query_index: usize) -> StarkToolsResult<Path<MerkleConfig>> {
 
     // let bn: BigIntCanonical<4> = bytes_to_bigints_canonical(proof[0]);
     // let sibling = ark_ed_on_bls12_381::Fq::new(bn);
+    let (sibling, auth_path) = proof.split_first().ok_or_else(|| StarkToolsError::MalformedProof("a path holds at least the leaf sibling".to_string()))?;
     Ok(Path {
-            leaf_sibling_hash: bytes_to_bls(proof[0]),
-            auth_path: proof[1..].iter().map(|e| bytes_to_bls(*e)).collect(),
+            leaf_sibling_hash: bytes_to_bls(*sibling)?,
+            auth_path: auth_path.iter().map(|e| bytes_to_bls(*e)).collect::<StarkToolsResult<Vec<Root>>>()?,
             leaf_index: query_index,
         })
 }
 
-pub fn prove_leaf_index <F: PrimeField> (
-    commited_poly: &Commited<PolynomialPoints<F>>, index: u64) -> Result<(F, Path<MerkleConfig>), String> {
-    let roots = commited_poly.data.roots_preimage.as_ref().ok_or_else(|| format!("roots_preimage is None!"))?;
-    let queried_x = roots.get_by_right(&index).ok_or_else(|| format!("could not find index {index}"))?;
-    let leaf_value = commited_poly.data.points.get(queried_x).and_then(|y| Some(y.get_y())).ok_or_else(|| format!("could not find leaf_value for index {index}"))?;
-    let path = commited_poly.ptree.generate_proof(index as usize).map_err(|e| format!("faild to generate proof! {e}"))?;
-    Ok((leaf_value, path))
+/// Serialises bare digests (e.g. the path above a block of leaves) like `path_to_bytes` does.
+pub fn digests_to_bytes(digests: &[Root]) -> StarkToolsResult<Vec<[u8; 32]>> {
+    digests.iter().map(|digest| {
+        let mut bytes: [u8; 32] = digest.to_sponge_bytes_as_vec().try_into().map_err(digest_length_error)?;
+        bytes.reverse();
+        Ok(bytes)
+    }).collect()
 }
 
+fn digest_length_error(bytes: Vec<u8>) -> StarkToolsError {
+    StarkToolsError::MalformedProof(format!("a digest serialises to {} bytes instead of 32", bytes.len()))
+}
 
-pub fn bytes_to_bigints<const N: usize>(bytes: Vec<u8>) -> BigInt<N> {
-    assert_eq!(bytes.len() / 8, N);
-    let mut res = [0u64; N];
-    for i in 0..N {
-        let mut sum: u64 = 0;
-        for j in 0..8 {
-            sum = (sum << 8) | (bytes[i * 8 + j] as u64);
-        }
-        res[i] = sum;
-    }
-    res.reverse();
-
-    BigInt::new(res)
+/// The evaluation stored at domain index `index`.
+pub fn leaf_value <F: PrimeField> (
+    commited_poly: &Commited<PolynomialPoints<F>>, index: u64) -> StarkToolsResult<F> {
+    let roots = commited_poly.data.roots_preimage.as_ref().ok_or_else(|| StarkToolsError::InvalidDomain("roots_preimage is None!".to_string()))?;
+    let queried_x = roots.get_by_right(&index).ok_or_else(|| StarkToolsError::InvalidDomain(format!("could not find index {index}")))?;
+    commited_poly.data.points.get(queried_x).map(|y| y.get_y()).ok_or_else(|| StarkToolsError::InvalidDomain(format!("could not find leaf_value for index {index}")))
 }
 
+pub fn prove_leaf_index <F: PrimeField> (
+    commited_poly: &Commited<PolynomialPoints<F>>, index: u64) -> StarkToolsResult<(F, Path<MerkleConfig>)> {
+    let leaf_value = leaf_value(commited_poly, index)?;
+    let path = commited_poly.ptree.generate_proof(commited_poly.leaf_position(index) as usize)?;
+    Ok((leaf_value, path))
+}
 
 
 pub fn bytes_to_bigints_canonical<const N1: usize, const N2: usize>(bytes: [u8; N1]) -> BigInt<N2> {
@@ -68,6 +74,27 @@ pub fn bytes_to_bigints_canonical<const N1: usize, const N2: usize>(bytes: [u8;
     BigInt::new(res)
 }
 
-pub fn bytes_to_bls(bytes: [u8; 32]) -> ark_ed_on_bls12_381::Fq {
-    ark_ed_on_bls12_381::Fq::new(bytes_to_bigints_canonical::<32, 4>(bytes))
+/// Reads a big-endian digest, rejecting encodings of values past the modulus
+/// so that every digest has exactly one encoding.
+pub fn bytes_to_bls(bytes: [u8; 32]) -> StarkToolsResult<ark_ed_on_bls12_381::Fq> {
+    ark_ed_on_bls12_381::Fq::from_bigint(bytes_to_bigints_canonical::<32, 4>(bytes))
+        .ok_or_else(|| StarkToolsError::MalformedProof(format!("digest {} is not a canonical field element", hex::encode(bytes))))
+}
+
+
+/// Serialises a cap the way the prover absorbs commitments: each digest in sponge (little-endian) byte order.
+pub fn cap_to_bytes(cap: &MerkleCap) -> Vec<u8> {
+    cap.iter().flat_map(|root| root.to_sponge_bytes_as_vec()).collect()
+}
+
+/// Reads a cap written by `cap_to_bytes`. Fails on a trailing partial digest or a non-canonical one.
+pub fn bytes_to_cap(bytes: &[u8]) -> StarkToolsResult<MerkleCap> {
+    if !bytes.len().is_multiple_of(DIGEST_BYTES) {
+        return Err(StarkToolsError::MalformedProof(format!("a cap of {} bytes is not made of {DIGEST_BYTES} byte digests", bytes.len())));
+    }
+    bytes.chunks_exact(DIGEST_BYTES).map(|chunk| {
+        let mut root_bytes: [u8; 32] = chunk.try_into().unwrap();
+        root_bytes.reverse();
+        bytes_to_bls(root_bytes)
+    }).collect()
 }
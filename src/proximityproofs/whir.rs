@@ -0,0 +1,548 @@
+use ark_ec::CurveGroup;
+use ark_ff::{BigInteger, PrimeField};
+use spongefish::{codecs::arkworks_algebra::{FieldDomainSeparator, FieldToUnitDeserialize, GroupDomainSeparator, GroupToUnitDeserialize, GroupToUnitSerialize, UnitToField}, ByteDomainSeparator, BytesToUnitDeserialize, BytesToUnitSerialize, CommonUnitToBytes, DomainSeparator, DuplexSpongeInterface, ProofError, ProofResult, ProverState, UnitToBytes, VerifierState};
+use crate::{commitable::{Commitable, Commited, LeafOrdering}, error::{StarkToolsError, StarkToolsResult}, merkletree::{MerkleCap, PedersenTreeConfig}, polynomial::{Polynomial, PolynomialCoefficient, PolynomialPoints}};
+use crate::proximityproofs::{cosets::{fold_coset_rounds, read_field_element, CosetOpener}, narg_proximityproof::{ProximityProofDomainSeparator, ProximityProofProver, ProximityProofVerifier}, pow::{read_and_verify_pow, write_pow, MAX_POW_BITS, POW_CHALLENGE_BYTES, POW_NONCE_BYTES}, queries::{sample_queries, QUERY_SEED_BYTES}, stir::StirConfig, utils::{bytes_to_cap, cap_to_bytes}};
+use std::marker::PhantomData;
+
+/// WHIR takes the same parameters as STIR: each round folds `log2(folding_factor)` variables, one sumcheck round each,
+/// and halves the evaluation domain. `ood_samples` also applies to the committed input.
+pub type WhirConfig = StirConfig;
+
+/// A WHIR proof for the codeword of a polynomial with `degree_bound = 2^m` coefficients, read as a multilinear
+/// polynomial in `m` variables (see `PolynomialCoefficient::evaluate_multilinear`). Every claim about the polynomial,
+/// out-of-domain samples and shift queries included, is a weighted sum `sum_b eq(point, b) * f(b)` over the hypercube;
+/// a sumcheck folds those claims along with the codeword, so evaluation claims come at no extra cost.
+pub struct WHIRProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField> {
+    pub config: WhirConfig,
+    pub pedersen_config: PedersenTreeConfig,
+    pub leaf_ordering: LeafOrdering,
+    marker_type1: PhantomData<H>,
+    marker_type2: PhantomData<G>,
+    marker_field: PhantomData<F>
+}
+
+/// The point `(x, x^2, x^4, ...)` at which a multilinear polynomial in `num_variables` variables
+/// takes the value of its univariate counterpart at `x`.
+fn pow_point<F: PrimeField>(x: F, num_variables: usize) -> Vec<F> {
+    std::iter::successors(Some(x), |power| Some(power.square())).take(num_variables).collect()
+}
+
+/// `eq(a, b) = prod_j (a_j * b_j + (1 - a_j) * (1 - b_j))`, which is 1 on `a = b` over the hypercube and 0 elsewhere.
+fn eq<F: PrimeField>(a: &[F], b: &[F]) -> F {
+    a.iter().zip(b).map(|(a, b)| *a * b + (F::ONE - a) * (F::ONE - b)).product()
+}
+
+/// `eq(point, b)` for every `b` of the hypercube, with bit `j` of the index holding variable `j`.
+fn eq_evaluations<F: PrimeField>(point: &[F]) -> Vec<F> {
+    let mut evaluations = vec![F::ONE];
+    for x in point {
+        let low: Vec<F> = evaluations.iter().map(|e| *e * (F::ONE - x)).collect();
+        let high: Vec<F> = evaluations.iter().map(|e| *e * x).collect();
+        evaluations = low.into_iter().chain(high).collect();
+    }
+    evaluations
+}
+
+/// The evaluations over the hypercube of the multilinear polynomial with these coefficients.
+fn hypercube_evaluations<F: PrimeField>(coefficients: &[F]) -> Vec<F> {
+    let mut evaluations = coefficients.to_vec();
+    let mut bit = 1;
+    while bit < evaluations.len() {
+        for index in 0..evaluations.len() {
+            if index & bit != 0 {
+                let low = evaluations[index ^ bit];
+                evaluations[index] += low;
+            }
+        }
+        bit <<= 1;
+    }
+    evaluations
+}
+
+/// Fixes the first variable of hypercube evaluations to `r`.
+fn fold_evaluations<F: PrimeField>(evaluations: &[F], r: F) -> Vec<F> {
+    evaluations.chunks(2).map(|pair| pair[0] + r * (pair[1] - pair[0])).collect()
+}
+
+/// Fixes the first variable of the multilinear polynomial to `r`, which is a binary FRI fold of its coefficients.
+fn fold_coefficients<F: PrimeField>(polynomial: &PolynomialCoefficient<F>, r: F) -> PolynomialCoefficient<F> {
+    let coefficients: Vec<F> = polynomial.coefficients.chunks(2).map(|pair| pair[0] + r * pair[1]).collect();
+    PolynomialCoefficient { degree: coefficients.len() as u64 - 1, coefficients }
+}
+
+/// The sumcheck polynomial `h(X) = sum_b w(X, b) * f(X, b)`, of degree 2, as its values at 0, 1 and 2.
+fn sumcheck_polynomial<F: PrimeField>(evaluations: &[F], weights: &[F]) -> [F; 3] {
+    let mut h = [F::ZERO; 3];
+    for (f, w) in evaluations.chunks(2).zip(weights.chunks(2)) {
+        h[0] += f[0] * w[0];
+        h[1] += f[1] * w[1];
+        h[2] += (f[1].double() - f[0]) * (w[1].double() - w[0]);
+    }
+    h
+}
+
+/// Interpolates the sumcheck polynomial from its values at 0, 1 and 2 and evaluates it at `r`.
+fn evaluate_sumcheck_polynomial<F: PrimeField>(h: &[F; 3], r: F) -> F {
+    let two_inverse = F::from(2u64).inverse().unwrap();
+    h[0] * (r - F::ONE) * (r - F::from(2u64)) * two_inverse
+        - h[1] * r * (r - F::from(2u64))
+        + h[2] * r * (r - F::ONE) * two_inverse
+}
+
+impl<F, G, H> WHIRProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField> {
+
+    /// Fails when the field has no evaluation domain as large as the one `config` needs.
+    pub fn new(config: WhirConfig, pedersen_config: PedersenTreeConfig) -> StarkToolsResult<Self> {
+        if config.domain_size(0).trailing_zeros() > F::TWO_ADICITY {
+            return Err(StarkToolsError::MissingRootOfUnity { order: config.domain_size(0) });
+        }
+        if config.pow_bits > MAX_POW_BITS {
+            return Err(StarkToolsError::InvalidConfig(format!("{} bits of proof of work, at most {MAX_POW_BITS} are supported", config.pow_bits)));
+        }
+        Ok(Self {
+            config,
+            pedersen_config,
+            leaf_ordering: LeafOrdering::Natural,
+            marker_type1: PhantomData,
+            marker_type2: PhantomData,
+            marker_field: PhantomData
+        })
+    }
+
+    /// With `LeafOrdering::BitReversed` every folding coset forms one block of leaves opened by a single Merkle path.
+    /// The committed input polynomial must use the same ordering.
+    pub fn with_leaf_ordering(mut self, leaf_ordering: LeafOrdering) -> Self {
+        self.leaf_ordering = leaf_ordering;
+        self
+    }
+
+    fn opener(&self) -> CosetOpener<'_, F> {
+        CosetOpener::new(&self.pedersen_config, self.leaf_ordering, self.config.folding_factor)
+    }
+
+    fn field_bytes(&self) -> usize {
+        self.opener().field_bytes()
+    }
+
+    /// Number of variables of the function folded in `round`.
+    fn num_variables(&self, round: usize) -> usize {
+        self.config.degree(round).trailing_zeros() as usize
+    }
+
+    /// Sumcheck rounds, and so variables folded, per round.
+    fn sumcheck_rounds(&self) -> usize {
+        self.config.folding_factor.trailing_zeros() as usize
+    }
+
+    /// Point `index` of the domain folded in `round`, the subgroup of `domain_size(round)` points.
+    fn domain_point(&self, round: usize, index: u64) -> F {
+        F::get_root_of_unity(self.config.domain_size(round)).unwrap().pow([index])
+    }
+
+    fn squeeze_field_element(&self, prover_state: &mut ProverState<H>) -> ProofResult<F> {
+        let mut bytes = vec![0u8; self.field_bytes()];
+        prover_state.fill_challenge_bytes(&mut bytes)?;
+        Ok(F::from_be_bytes_mod_order(&bytes))
+    }
+
+    fn challenge_field_element(&self, verifier_state: &mut VerifierState<H>) -> ProofResult<F> {
+        let mut bytes = vec![0u8; self.field_bytes()];
+        verifier_state.fill_challenge_bytes(&mut bytes)?;
+        Ok(F::from_be_bytes_mod_order(&bytes))
+    }
+
+    fn add_query_sampling(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
+        let mut ds = ds;
+        if self.config.pow_bits > 0 {
+            ds = ds.challenge_bytes(POW_CHALLENGE_BYTES, "proof of work challenge");
+            ds = ds.add_bytes(POW_NONCE_BYTES, "proof of work nonce");
+        }
+        ds.challenge_bytes(QUERY_SEED_BYTES, "query seed")
+    }
+
+    /// Grinds the proof of work if any, then samples the cosets queried in `round`.
+    fn sample_queries_prover(&self, prover_state: &mut ProverState<H>, round: usize) -> Result<Vec<u64>, ProofError> {
+        if self.config.pow_bits > 0 {
+            write_pow(self.config.pow_bits, prover_state)?;
+        }
+        let query_seed = prover_state.challenge_bytes::<QUERY_SEED_BYTES>()?;
+        Ok(sample_queries(&query_seed, self.config.queries[round] as usize, self.config.domain_size(round) / self.config.folding_factor))
+    }
+
+    fn sample_queries_verifier(&self, verifier_state: &mut VerifierState<H>, round: usize) -> Result<Vec<u64>, ProofError> {
+        if self.config.pow_bits > 0 {
+            read_and_verify_pow(self.config.pow_bits, verifier_state)?;
+        }
+        let query_seed = verifier_state.challenge_bytes::<QUERY_SEED_BYTES>()?;
+        Ok(sample_queries(&query_seed, self.config.queries[round] as usize, self.config.domain_size(round) / self.config.folding_factor))
+    }
+
+    /// Domain separator for `prove_evaluations`/`verify_evaluations` at `num_points` points.
+    pub fn new_evaluation_proof(&self, num_points: usize) -> DomainSeparator<H> {
+        let ds = DomainSeparator::new(&format!(
+            "WHIR proximity proof, merkle cap height {}, {:?} leaves",
+            self.pedersen_config.cap_height,
+            self.leaf_ordering
+        ));
+        let ds = self.add_pp_statement(ds);
+        self.add_evaluations_domsep(ds, num_points)
+    }
+
+    fn add_evaluations_domsep(&self, ds: DomainSeparator<H>, num_points: usize) -> DomainSeparator<H> {
+        let mut ds = ds;
+        let s = self.field_bytes();
+        if num_points > 0 {
+            ds = ds.add_bytes(s * num_points * self.num_variables(0), "public evaluation points");
+        }
+        for _ in 0..self.config.ood_samples {
+            ds = ds.challenge_bytes(s, "out-of-domain point");
+        }
+        ds = ds.add_bytes(s * self.config.ood_samples as usize, "out-of-domain evaluations");
+        if num_points > 0 {
+            ds = ds.add_bytes(s * num_points, "evaluations");
+        }
+        ds = ds.challenge_bytes(s, "combination randomness");
+        for i in 0..self.config.num_rounds() {
+            for _ in 0..self.sumcheck_rounds() {
+                ds = ds.add_bytes(3 * s, "sumcheck polynomial");
+                ds = ds.challenge_bytes(s, "folding randomness");
+            }
+            let last_round = i + 1 == self.config.num_rounds();
+            if last_round {
+                ds = ds.add_bytes(s * (self.config.final_degree as usize + 1), "final polynomial coefficients");
+                ds = self.add_query_sampling(ds);
+            } else {
+                ds = ds.add_bytes(32 * self.opener().cap_size(self.config.domain_size(i + 1)), "fold commitment");
+                for _ in 0..self.config.ood_samples {
+                    ds = ds.challenge_bytes(s, "out-of-domain point");
+                }
+                ds = ds.add_bytes(s * self.config.ood_samples as usize, "out-of-domain evaluations");
+                ds = self.add_query_sampling(ds);
+                ds = ds.challenge_bytes(s, "combination randomness");
+            }
+            for _ in 0..self.config.queries[i] {
+                ds = self.opener().add_coset_opening(ds, self.config.domain_size(i));
+            }
+        }
+        ds
+    }
+
+    /// Proves that the committed codeword is close to a polynomial of degree below `degree_bound` and returns the
+    /// evaluations of that polynomial, as a multilinear polynomial, at `points`. The transcript must come from
+    /// `new_evaluation_proof` with as many points and the commitment added as public bytes; the points are absorbed
+    /// before anything else. Fails if a point does not have `log2(degree_bound)` coordinates.
+    pub fn prove_evaluations(
+        &self,
+        prover_state: &mut ProverState<H>,
+        polynomial: &Commited<PolynomialPoints<F>>,
+        points: &[Vec<F>],
+    ) -> StarkToolsResult<Vec<F>> {
+        if polynomial.ordering != self.leaf_ordering {
+            return Err(StarkToolsError::InvalidConfig(format!("polynomial committed with {:?} leaves, expected {:?}", polynomial.ordering, self.leaf_ordering)));
+        }
+        let num_variables = self.num_variables(0);
+        if let Some(point) = points.iter().find(|point| point.len() != num_variables) {
+            return Err(StarkToolsError::InvalidConfig(format!("evaluation point with {} coordinates for a polynomial in {num_variables} variables", point.len())));
+        }
+        for coordinate in points.iter().flatten() {
+            prover_state.public_bytes(&coordinate.into_bigint().to_bytes_be())?;
+        }
+        let mut function = polynomial.data.clone().ifft(self.config.blowup)?;
+        function.coefficients.resize(self.config.degree_bound as usize, F::ZERO);
+
+        // the committed function is pinned down out of domain like every later fold, then joined by the claims
+        let mut constraints: Vec<(Vec<F>, F)> = vec![];
+        let mut ood_points = vec![];
+        for _ in 0..self.config.ood_samples {
+            ood_points.push(self.squeeze_field_element(prover_state)?);
+        }
+        for point in ood_points {
+            let value = function.evaluate(point);
+            prover_state.add_bytes(&value.into_bigint().to_bytes_be())?;
+            constraints.push((pow_point(point, num_variables), value));
+        }
+        let values = points.iter().map(|point| function.evaluate_multilinear(point)).collect::<StarkToolsResult<Vec<F>>>()?;
+        for (point, value) in points.iter().zip(&values) {
+            prover_state.add_bytes(&value.into_bigint().to_bytes_be())?;
+            constraints.push((point.clone(), *value));
+        }
+        let combination_randomness = self.squeeze_field_element(prover_state)?;
+        let mut weights = vec![F::ZERO; self.config.degree_bound as usize];
+        let mut sum = F::ZERO;
+        let mut coefficient = F::ONE;
+        for (point, value) in &constraints {
+            for (w, e) in weights.iter_mut().zip(eq_evaluations(point)) {
+                *w += coefficient * e;
+            }
+            sum += coefficient * value;
+            coefficient *= combination_randomness;
+        }
+
+        let mut evaluations = hypercube_evaluations(&function.coefficients);
+        // folds[i] is committed in round `i` and queried in round `i + 1`
+        let mut folds: Vec<Commited<PolynomialPoints<F>>> = vec![];
+        for i in 0..self.config.num_rounds() {
+            let mut folding_randomness = vec![];
+            for _ in 0..self.sumcheck_rounds() {
+                let h = sumcheck_polynomial(&evaluations, &weights);
+                for value in &h {
+                    prover_state.add_bytes(&value.into_bigint().to_bytes_be())?;
+                }
+                let r = self.squeeze_field_element(prover_state)?;
+                sum = evaluate_sumcheck_polynomial(&h, r);
+                evaluations = fold_evaluations(&evaluations, r);
+                weights = fold_evaluations(&weights, r);
+                function = fold_coefficients(&function, r);
+                folding_randomness.push(r);
+            }
+            let max_index = self.config.domain_size(i);
+            if i + 1 == self.config.num_rounds() {
+                for coefficient in &function.coefficients {
+                    prover_state.add_bytes(&coefficient.into_bigint().to_bytes_be())?;
+                }
+                let queries = self.sample_queries_prover(prover_state, i)?;
+                let oracle = folds.last().unwrap_or(polynomial);
+                for query in queries {
+                    self.opener().write_coset_opening(oracle, query, max_index, prover_state)?;
+                }
+                break;
+            }
+
+            let rate = self.config.domain_size(i + 1) / self.config.degree(i + 1);
+            let commited = function.clone().fft(rate)?.commit_with_ordering(&self.pedersen_config, self.leaf_ordering)?;
+            prover_state.add_bytes(&cap_to_bytes(&commited.ptree.cap()))?;
+            let num_variables = self.num_variables(i + 1);
+            let mut constraints: Vec<(Vec<F>, F)> = vec![];
+            let mut ood_points = vec![];
+            for _ in 0..self.config.ood_samples {
+                ood_points.push(self.squeeze_field_element(prover_state)?);
+            }
+            for point in ood_points {
+                let value = function.evaluate(point);
+                prover_state.add_bytes(&value.into_bigint().to_bytes_be())?;
+                constraints.push((pow_point(point, num_variables), value));
+            }
+            let queries = self.sample_queries_prover(prover_state, i)?;
+            let combination_randomness = self.squeeze_field_element(prover_state)?;
+            let oracle = folds.last().unwrap_or(polynomial);
+            for query in &queries {
+                self.opener().write_coset_opening(oracle, *query, max_index, prover_state)?;
+                let point = self.domain_point(i, *query).pow([self.config.folding_factor]);
+                constraints.push((pow_point(point, num_variables), function.evaluate(point)));
+            }
+            let mut coefficient = combination_randomness;
+            for (point, value) in &constraints {
+                for (w, e) in weights.iter_mut().zip(eq_evaluations(point)) {
+                    *w += coefficient * e;
+                }
+                sum += coefficient * value;
+                coefficient *= combination_randomness;
+            }
+            folds.push(commited);
+        }
+        debug_assert_eq!(sum, evaluations.iter().zip(&weights).map(|(f, w)| *f * w).sum::<F>());
+        Ok(values)
+    }
+
+    /// Verifies a `prove_evaluations` proof that the polynomial committed to by `commitment` takes `values` at `points`.
+    pub fn verify_evaluations(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &MerkleCap,
+        points: &[Vec<F>],
+        values: &[F],
+    ) -> ProofResult<()> {
+        if self.read_evaluations(verifier_state, commitment, points)? != values {
+            return Err(ProofError::InvalidProof);
+        }
+        Ok(())
+    }
+
+    /// Verifies a `prove_evaluations` proof and returns the evaluations it proves.
+    fn read_evaluations(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &MerkleCap,
+        points: &[Vec<F>],
+    ) -> ProofResult<Vec<F>> {
+        let num_variables = self.num_variables(0);
+        if points.iter().any(|point| point.len() != num_variables) {
+            return Err(ProofError::InvalidProof);
+        }
+        for coordinate in points.iter().flatten() {
+            verifier_state.public_bytes(&coordinate.into_bigint().to_bytes_be())?;
+        }
+        // the claim is sum_b w(b) * f(b) = sum, with w the sum of the terms coefficient * eq(point, b);
+        // each term lives on the variables left when it was added and the sumcheck fixes them one by one
+        let mut terms: Vec<(F, Vec<F>)> = vec![];
+        let mut constraints: Vec<(Vec<F>, F)> = vec![];
+        let mut ood_points = vec![];
+        for _ in 0..self.config.ood_samples {
+            ood_points.push(self.challenge_field_element(verifier_state)?);
+        }
+        for point in ood_points {
+            constraints.push((pow_point(point, num_variables), read_field_element(self.field_bytes(), verifier_state)?));
+        }
+        let mut evaluations = vec![];
+        for point in points {
+            let value = read_field_element(self.field_bytes(), verifier_state)?;
+            constraints.push((point.clone(), value));
+            evaluations.push(value);
+        }
+        let combination_randomness = self.challenge_field_element(verifier_state)?;
+        let mut sum = F::ZERO;
+        let mut coefficient = F::ONE;
+        for (point, value) in constraints {
+            sum += coefficient * value;
+            terms.push((coefficient, point));
+            coefficient *= combination_randomness;
+        }
+
+        let mut folding_randomness: Vec<F> = vec![];
+        let mut oracle = commitment.clone();
+        for i in 0..self.config.num_rounds() {
+            let mut round_randomness = vec![];
+            for _ in 0..self.sumcheck_rounds() {
+                let mut h = [F::ZERO; 3];
+                for value in h.iter_mut() {
+                    *value = read_field_element(self.field_bytes(), verifier_state)?;
+                }
+                if h[0] + h[1] != sum {
+                    return Err(ProofError::InvalidProof);
+                }
+                let r = self.challenge_field_element(verifier_state)?;
+                sum = evaluate_sumcheck_polynomial(&h, r);
+                round_randomness.push(r);
+            }
+            folding_randomness.extend(&round_randomness);
+            let max_index = self.config.domain_size(i);
+
+            if i + 1 == self.config.num_rounds() {
+                let coefficients = (0..=self.config.final_degree)
+                    .map(|_| read_field_element(self.field_bytes(), verifier_state))
+                    .collect::<Result<Vec<F>, ProofError>>()?;
+                let final_polynomial = PolynomialCoefficient { degree: self.config.final_degree, coefficients };
+                let queries = self.sample_queries_verifier(verifier_state, i)?;
+                for query in queries {
+                    let coset_values = self.opener().read_coset_opening(verifier_state, &oracle, query, max_index)?;
+                    let x = self.domain_point(i, query);
+                    let folded = fold_coset_rounds(&coset_values, x, &round_randomness);
+                    if folded != final_polynomial.evaluate(x.pow([self.config.folding_factor])) {
+                        return Err(ProofError::InvalidProof);
+                    }
+                }
+                // what is left of each term after the sumcheck fixed all but the final variables
+                let final_variables = self.num_variables(i + 1);
+                let expected = terms.iter().map(|(coefficient, point)| {
+                    let fixed = point.len() - final_variables;
+                    let randomness = &folding_randomness[folding_randomness.len() - fixed..];
+                    Ok(*coefficient * eq(&point[..fixed], randomness) * final_polynomial.evaluate_multilinear(&point[fixed..])?)
+                }).sum::<StarkToolsResult<F>>()?;
+                if sum != expected {
+                    return Err(ProofError::InvalidProof);
+                }
+                break;
+            }
+
+            let mut fold_commitment_bytes = vec![0; 32 * self.opener().cap_size(self.config.domain_size(i + 1))];
+            verifier_state.fill_next_bytes(&mut fold_commitment_bytes)?;
+            let num_variables = self.num_variables(i + 1);
+            let mut constraints: Vec<(Vec<F>, F)> = vec![];
+            let mut ood_points = vec![];
+            for _ in 0..self.config.ood_samples {
+                ood_points.push(self.challenge_field_element(verifier_state)?);
+            }
+            for point in ood_points {
+                constraints.push((pow_point(point, num_variables), read_field_element(self.field_bytes(), verifier_state)?));
+            }
+            let queries = self.sample_queries_verifier(verifier_state, i)?;
+            let combination_randomness = self.challenge_field_element(verifier_state)?;
+            for query in &queries {
+                let coset_values = self.opener().read_coset_opening(verifier_state, &oracle, *query, max_index)?;
+                let x = self.domain_point(i, *query);
+                let point = x.pow([self.config.folding_factor]);
+                constraints.push((pow_point(point, num_variables), fold_coset_rounds(&coset_values, x, &round_randomness)));
+            }
+            let mut coefficient = combination_randomness;
+            for (point, value) in constraints {
+                sum += coefficient * value;
+                terms.push((coefficient, point));
+                coefficient *= combination_randomness;
+            }
+            oracle = bytes_to_cap(&fold_commitment_bytes).map_err(|_| ProofError::InvalidProof)?;
+        }
+        Ok(evaluations)
+    }
+}
+
+impl<F, G, H> ProximityProofDomainSeparator<G, H> for WHIRProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>
+{
+    fn new_pp_proof(&self) -> DomainSeparator<H> {
+        self.new_evaluation_proof(0)
+    }
+
+    fn add_pp_statement(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
+        ds.add_bytes(32 * self.opener().cap_size(self.config.domain_size(0)), "public commitment (C)")
+            .ratchet()
+    }
+
+    fn add_pp_domsep(&self, ds: DomainSeparator<H>) -> DomainSeparator<H> {
+        self.add_evaluations_domsep(ds, 0)
+    }
+}
+
+impl<'b, H, G, P, F, Raw>
+ProximityProofProver<'b, H, G, P, F, Raw> for WHIRProtocol<F, G, H> where
+    F: PrimeField,
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    P: Polynomial<F, Raw>,
+    ProverState<H>: GroupToUnitSerialize<G> + UnitToField<G::ScalarField>,
+    Self: ProximityProofDomainSeparator<G, H> {
+    fn prove(
+        &self,
+        prover_state: &'b mut ProverState<H>,
+        polynomial: &Commited<P>,
+    ) -> ProofResult<&'b [u8]> {
+        let polynomial = Commited {
+            data: polynomial.data.clone().fft(self.config.blowup)?,
+            ptree: polynomial.ptree.clone(),
+            ordering: polynomial.ordering
+        };
+        self.prove_evaluations(prover_state, &polynomial, &[])?;
+        Ok(prover_state.narg_string())
+    }
+}
+
+impl<'b, F, H, G>
+ProximityProofVerifier<'b, H, G, F, MerkleCap> for WHIRProtocol<F, G, H> where
+    F: PrimeField,
+    H: DuplexSpongeInterface,
+    G: CurveGroup,
+    ProverState<H>: GroupToUnitSerialize<G> + UnitToField<G::ScalarField>,
+    Self: ProximityProofDomainSeparator<G, H>,
+    for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<G>
+        + FieldToUnitDeserialize<G::ScalarField>
+        + UnitToField<G::ScalarField> {
+
+    fn verify(
+        &self,
+        verifier_state: &mut VerifierState<H>,
+        commitment: &'b MerkleCap,
+    ) -> ProofResult<()> {
+        self.read_evaluations(verifier_state, commitment, &[]).map(|_| ())
+    }
+}
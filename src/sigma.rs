@@ -0,0 +1,399 @@
+//! Sigma protocols: three-move proofs of knowledge made of a commitment, a challenge and a response,
+//! compiled into non-interactive proofs with `spongefish`.
+//!
+//! Every protocol here speaks in group elements and scalars of one `CurveGroup`, so protocols can be
+//! composed: `And` proves two statements under the same challenge and `Or` proves one of two statements
+//! without revealing which, by simulating the other (Cramer–Damgård–Schoenmakers).
+use ark_ec::CurveGroup;
+use ark_ff::UniformRand;
+use ark_serialize::CanonicalSerialize;
+use ark_std::rand::Rng;
+use spongefish::codecs::arkworks_algebra::{
+    DomainSeparator, DuplexSpongeInterface, FieldDomainSeparator, FieldToUnitDeserialize,
+    FieldToUnitSerialize, GroupDomainSeparator, GroupToUnitDeserialize, GroupToUnitSerialize, ProofError,
+    ProofResult, ProverState, UnitToField, VerifierState
+};
+
+/// A public-coin three-move protocol over the group `Group`, whose challenges are scalars.
+pub trait SigmaProtocol {
+    type Group: CurveGroup;
+    type Witness;
+    /// What the prover keeps between `commit` and `respond`.
+    type State;
+
+    /// The public points the proof is about, absorbed before the commitment.
+    fn statement(&self) -> Vec<Self::Group>;
+    /// Number of points in a commitment.
+    fn commitment_len(&self) -> usize;
+    /// Number of scalars in a response.
+    fn response_len(&self) -> usize;
+
+    fn commit<R: Rng + ?Sized>(&self, witness: &Self::Witness, rng: &mut R) -> (Vec<Self::Group>, Self::State);
+    fn respond(&self, witness: &Self::Witness, state: Self::State, challenge: Scalar<Self>) -> Vec<Scalar<Self>>;
+    /// Checks a transcript; `commitment` and `response` have the lengths this protocol announces.
+    fn verify(&self, commitment: &[Self::Group], challenge: Scalar<Self>, response: &[Scalar<Self>]) -> bool;
+    /// An accepting transcript for `challenge`, made without the witness and distributed like an honest one.
+    fn simulate<R: Rng + ?Sized>(&self, challenge: Scalar<Self>, rng: &mut R) -> (Vec<Self::Group>, Vec<Scalar<Self>>);
+}
+
+/// The scalar field of a protocol's group.
+pub type Scalar<P> = <<P as SigmaProtocol>::Group as ark_ec::PrimeGroup>::ScalarField;
+
+/// The commitment, challenge and response of a proof, as `read_proof` returns them.
+pub(crate) type Transcript<P> = (Vec<<P as SigmaProtocol>::Group>, Scalar<P>, Vec<Scalar<P>>);
+
+/// Extend the domain separator with a sigma protocol.
+pub trait SigmaDomainSeparator<P: SigmaProtocol> {
+    /// Shortcut: create a new proof for `protocol` with statement + proof.
+    fn new_sigma_proof(domsep: &str, protocol: &P) -> Self;
+    /// Add the statement of `protocol`.
+    fn add_sigma_statement(self, protocol: &P) -> Self;
+    /// Add the commitment, challenge and response of `protocol`.
+    fn add_sigma_domsep(self, protocol: &P) -> Self;
+}
+
+impl<P, H> SigmaDomainSeparator<P> for DomainSeparator<H>
+where
+    P: SigmaProtocol,
+    H: DuplexSpongeInterface,
+    Self: GroupDomainSeparator<P::Group> + FieldDomainSeparator<Scalar<P>>,
+{
+    fn new_sigma_proof(domsep: &str, protocol: &P) -> Self {
+        Self::new(domsep)
+            .add_sigma_statement(protocol)
+            .add_sigma_domsep(protocol)
+    }
+
+    fn add_sigma_statement(self, protocol: &P) -> Self {
+        self.add_points(protocol.statement().len(), "statement")
+            .ratchet()
+    }
+
+    fn add_sigma_domsep(self, protocol: &P) -> Self {
+        self.add_points(protocol.commitment_len(), "commitment")
+            .challenge_scalars(1, "challenge (c)")
+            .add_scalars(protocol.response_len(), "response")
+    }
+}
+
+/// Proves knowledge of `witness` for `protocol`, whose statement must already be in the transcript.
+pub fn prove<'a, P, H>(prover_state: &'a mut ProverState<H>, protocol: &P, witness: &P::Witness) -> ProofResult<&'a [u8]>
+where
+    P: SigmaProtocol,
+    H: DuplexSpongeInterface,
+    ProverState<H>: GroupToUnitSerialize<P::Group> + UnitToField<Scalar<P>> + FieldToUnitSerialize<Scalar<P>>,
+{
+    let (commitment, state) = protocol.commit(witness, prover_state.rng());
+    prover_state.add_points(&commitment)?;
+    let [challenge] = prover_state.challenge_scalars()?;
+    prover_state.add_scalars(&protocol.respond(witness, state, challenge))?;
+    Ok(prover_state.narg_string())
+}
+
+/// Bytes of a `prove` proof for `protocol`. The transcript reads only what the domain separator asks for,
+/// so callers holding the proof compare its length with this to reject bytes left over after the response.
+pub fn proof_length<P: SigmaProtocol>(protocol: &P) -> usize {
+    protocol.commitment_len() * P::Group::default().compressed_size()
+        + protocol.response_len() * Scalar::<P>::default().compressed_size()
+}
+
+/// Reads the commitment, challenge and response of a `prove` proof for `protocol` without checking them.
+pub(crate) fn read_proof<P, H>(verifier_state: &mut VerifierState<H>, protocol: &P) -> ProofResult<Transcript<P>>
+where
+    P: SigmaProtocol,
+    H: DuplexSpongeInterface,
+    for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<P::Group>
+        + FieldToUnitDeserialize<Scalar<P>>
+        + UnitToField<Scalar<P>>,
+{
+    let mut commitment = vec![P::Group::default(); protocol.commitment_len()];
+    verifier_state.fill_next_points(&mut commitment)?;
+    let [challenge] = verifier_state.challenge_scalars()?;
+    let mut response = vec![Scalar::<P>::default(); protocol.response_len()];
+    verifier_state.fill_next_scalars(&mut response)?;
+    Ok((commitment, challenge, response))
+}
+
+/// Verifies a `prove` proof for `protocol`, whose statement must already be in the transcript.
+pub fn verify<P, H>(verifier_state: &mut VerifierState<H>, protocol: &P) -> ProofResult<()>
+where
+    P: SigmaProtocol,
+    H: DuplexSpongeInterface,
+    for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<P::Group>
+        + FieldToUnitDeserialize<Scalar<P>>
+        + UnitToField<Scalar<P>>,
+{
+    let (commitment, challenge, response) = read_proof(verifier_state, protocol)?;
+    if protocol.verify(&commitment, challenge, &response) {
+        Ok(())
+    } else {
+        Err(ProofError::InvalidProof)
+    }
+}
+
+/// Knowledge of `x` with `public_key = generator * x`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Schnorr<G> {
+    pub generator: G,
+    pub public_key: G,
+}
+
+impl<G: CurveGroup> SigmaProtocol for Schnorr<G> {
+    type Group = G;
+    type Witness = G::ScalarField;
+    type State = G::ScalarField;
+
+    fn statement(&self) -> Vec<G> {
+        vec![self.generator, self.public_key]
+    }
+
+    fn commitment_len(&self) -> usize {
+        1
+    }
+
+    fn response_len(&self) -> usize {
+        1
+    }
+
+    fn commit<R: Rng + ?Sized>(&self, _witness: &G::ScalarField, rng: &mut R) -> (Vec<G>, G::ScalarField) {
+        let k = G::ScalarField::rand(rng);
+        (vec![self.generator * k], k)
+    }
+
+    fn respond(&self, witness: &G::ScalarField, k: G::ScalarField, challenge: G::ScalarField) -> Vec<G::ScalarField> {
+        vec![k + challenge * witness]
+    }
+
+    fn verify(&self, commitment: &[G], challenge: G::ScalarField, response: &[G::ScalarField]) -> bool {
+        self.generator * response[0] == commitment[0] + self.public_key * challenge
+    }
+
+    fn simulate<R: Rng + ?Sized>(&self, challenge: G::ScalarField, rng: &mut R) -> (Vec<G>, Vec<G::ScalarField>) {
+        let r = G::ScalarField::rand(rng);
+        (vec![self.generator * r - self.public_key * challenge], vec![r])
+    }
+}
+
+/// Chaum–Pedersen proof that `g_x = g * x` and `h_x = h * x` share the discrete logarithm `x`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dleq<G> {
+    pub g: G,
+    pub h: G,
+    pub g_x: G,
+    pub h_x: G,
+}
+
+impl<G: CurveGroup> SigmaProtocol for Dleq<G> {
+    type Group = G;
+    type Witness = G::ScalarField;
+    type State = G::ScalarField;
+
+    fn statement(&self) -> Vec<G> {
+        vec![self.g, self.h, self.g_x, self.h_x]
+    }
+
+    fn commitment_len(&self) -> usize {
+        2
+    }
+
+    fn response_len(&self) -> usize {
+        1
+    }
+
+    fn commit<R: Rng + ?Sized>(&self, _witness: &G::ScalarField, rng: &mut R) -> (Vec<G>, G::ScalarField) {
+        let k = G::ScalarField::rand(rng);
+        (vec![self.g * k, self.h * k], k)
+    }
+
+    fn respond(&self, witness: &G::ScalarField, k: G::ScalarField, challenge: G::ScalarField) -> Vec<G::ScalarField> {
+        vec![k + challenge * witness]
+    }
+
+    fn verify(&self, commitment: &[G], challenge: G::ScalarField, response: &[G::ScalarField]) -> bool {
+        self.g * response[0] == commitment[0] + self.g_x * challenge
+            && self.h * response[0] == commitment[1] + self.h_x * challenge
+    }
+
+    fn simulate<R: Rng + ?Sized>(&self, challenge: G::ScalarField, rng: &mut R) -> (Vec<G>, Vec<G::ScalarField>) {
+        let r = G::ScalarField::rand(rng);
+        (vec![self.g * r - self.g_x * challenge, self.h * r - self.h_x * challenge], vec![r])
+    }
+}
+
+/// Okamoto's proof of knowledge of an opening `(m, r)` of the Pedersen commitment `commitment = g * m + h * r`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Okamoto<G> {
+    pub g: G,
+    pub h: G,
+    pub commitment: G,
+}
+
+impl<G: CurveGroup> SigmaProtocol for Okamoto<G> {
+    type Group = G;
+    type Witness = (G::ScalarField, G::ScalarField);
+    type State = (G::ScalarField, G::ScalarField);
+
+    fn statement(&self) -> Vec<G> {
+        vec![self.g, self.h, self.commitment]
+    }
+
+    fn commitment_len(&self) -> usize {
+        1
+    }
+
+    fn response_len(&self) -> usize {
+        2
+    }
+
+    fn commit<R: Rng + ?Sized>(&self, _witness: &Self::Witness, rng: &mut R) -> (Vec<G>, Self::State) {
+        let (a, b) = (G::ScalarField::rand(rng), G::ScalarField::rand(rng));
+        (vec![self.g * a + self.h * b], (a, b))
+    }
+
+    fn respond(&self, (m, r): &Self::Witness, (a, b): Self::State, challenge: G::ScalarField) -> Vec<G::ScalarField> {
+        vec![a + challenge * m, b + challenge * r]
+    }
+
+    fn verify(&self, commitment: &[G], challenge: G::ScalarField, response: &[G::ScalarField]) -> bool {
+        self.g * response[0] + self.h * response[1] == commitment[0] + self.commitment * challenge
+    }
+
+    fn simulate<R: Rng + ?Sized>(&self, challenge: G::ScalarField, rng: &mut R) -> (Vec<G>, Vec<G::ScalarField>) {
+        let (s, t) = (G::ScalarField::rand(rng), G::ScalarField::rand(rng));
+        (vec![self.g * s + self.h * t - self.commitment * challenge], vec![s, t])
+    }
+}
+
+/// Both statements, answered under the same challenge. Commitments and responses are concatenated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct And<P0, P1>(pub P0, pub P1);
+
+impl<P0, P1> SigmaProtocol for And<P0, P1>
+where
+    P0: SigmaProtocol,
+    P1: SigmaProtocol<Group = P0::Group>,
+{
+    type Group = P0::Group;
+    type Witness = (P0::Witness, P1::Witness);
+    type State = (P0::State, P1::State);
+
+    fn statement(&self) -> Vec<Self::Group> {
+        [self.0.statement(), self.1.statement()].concat()
+    }
+
+    fn commitment_len(&self) -> usize {
+        self.0.commitment_len() + self.1.commitment_len()
+    }
+
+    fn response_len(&self) -> usize {
+        self.0.response_len() + self.1.response_len()
+    }
+
+    fn commit<R: Rng + ?Sized>(&self, (w0, w1): &Self::Witness, rng: &mut R) -> (Vec<Self::Group>, Self::State) {
+        let (c0, s0) = self.0.commit(w0, rng);
+        let (c1, s1) = self.1.commit(w1, rng);
+        ([c0, c1].concat(), (s0, s1))
+    }
+
+    fn respond(&self, (w0, w1): &Self::Witness, (s0, s1): Self::State, challenge: Scalar<Self>) -> Vec<Scalar<Self>> {
+        [self.0.respond(w0, s0, challenge), self.1.respond(w1, s1, challenge)].concat()
+    }
+
+    fn verify(&self, commitment: &[Self::Group], challenge: Scalar<Self>, response: &[Scalar<Self>]) -> bool {
+        let (k0, k1) = commitment.split_at(self.0.commitment_len());
+        let (r0, r1) = response.split_at(self.0.response_len());
+        self.0.verify(k0, challenge, r0) && self.1.verify(k1, challenge, r1)
+    }
+
+    fn simulate<R: Rng + ?Sized>(&self, challenge: Scalar<Self>, rng: &mut R) -> (Vec<Self::Group>, Vec<Scalar<Self>>) {
+        let (k0, r0) = self.0.simulate(challenge, rng);
+        let (k1, r1) = self.1.simulate(challenge, rng);
+        ([k0, k1].concat(), [r0, r1].concat())
+    }
+}
+
+/// A witness for one of the two statements of an `Or`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrWitness<W0, W1> {
+    Left(W0),
+    Right(W1),
+}
+
+/// The prover's side of an `Or` between commitment and response: the real branch's state
+/// and the simulated branch's challenge and response.
+pub enum OrState<S0, S1, F> {
+    Left(S0, F, Vec<F>),
+    Right(F, Vec<F>, S1),
+}
+
+/// One of the two statements, without revealing which. The prover simulates the other branch under a challenge
+/// of its choosing and answers the real one under the rest of the verifier's challenge; the response starts
+/// with the left branch's challenge, from which the verifier recovers the right one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Or<P0, P1>(pub P0, pub P1);
+
+impl<P0, P1> SigmaProtocol for Or<P0, P1>
+where
+    P0: SigmaProtocol,
+    P1: SigmaProtocol<Group = P0::Group>,
+{
+    type Group = P0::Group;
+    type Witness = OrWitness<P0::Witness, P1::Witness>;
+    type State = OrState<P0::State, P1::State, Scalar<P0>>;
+
+    fn statement(&self) -> Vec<Self::Group> {
+        [self.0.statement(), self.1.statement()].concat()
+    }
+
+    fn commitment_len(&self) -> usize {
+        self.0.commitment_len() + self.1.commitment_len()
+    }
+
+    fn response_len(&self) -> usize {
+        1 + self.0.response_len() + self.1.response_len()
+    }
+
+    fn commit<R: Rng + ?Sized>(&self, witness: &Self::Witness, rng: &mut R) -> (Vec<Self::Group>, Self::State) {
+        match witness {
+            OrWitness::Left(w0) => {
+                let (k0, s0) = self.0.commit(w0, rng);
+                let c1 = Scalar::<Self>::rand(rng);
+                let (k1, r1) = self.1.simulate(c1, rng);
+                ([k0, k1].concat(), OrState::Left(s0, c1, r1))
+            }
+            OrWitness::Right(w1) => {
+                let c0 = Scalar::<Self>::rand(rng);
+                let (k0, r0) = self.0.simulate(c0, rng);
+                let (k1, s1) = self.1.commit(w1, rng);
+                ([k0, k1].concat(), OrState::Right(c0, r0, s1))
+            }
+        }
+    }
+
+    fn respond(&self, witness: &Self::Witness, state: Self::State, challenge: Scalar<Self>) -> Vec<Scalar<Self>> {
+        match (witness, state) {
+            (OrWitness::Left(w0), OrState::Left(s0, c1, r1)) => {
+                let c0 = challenge - c1;
+                [vec![c0], self.0.respond(w0, s0, c0), r1].concat()
+            }
+            (OrWitness::Right(w1), OrState::Right(c0, r0, s1)) => {
+                [vec![c0], r0, self.1.respond(w1, s1, challenge - c0)].concat()
+            }
+            _ => panic!("the state of one branch answered with the witness of the other"),
+        }
+    }
+
+    fn verify(&self, commitment: &[Self::Group], challenge: Scalar<Self>, response: &[Scalar<Self>]) -> bool {
+        let (k0, k1) = commitment.split_at(self.0.commitment_len());
+        let (c0, response) = (response[0], &response[1..]);
+        let (r0, r1) = response.split_at(self.0.response_len());
+        self.0.verify(k0, c0, r0) && self.1.verify(k1, challenge - c0, r1)
+    }
+
+    fn simulate<R: Rng + ?Sized>(&self, challenge: Scalar<Self>, rng: &mut R) -> (Vec<Self::Group>, Vec<Scalar<Self>>) {
+        let c0 = Scalar::<Self>::rand(rng);
+        let (k0, r0) = self.0.simulate(c0, rng);
+        let (k1, r1) = self.1.simulate(challenge - c0, rng);
+        ([k0, k1].concat(), [vec![c0], r0, r1].concat())
+    }
+}
@@ -1,199 +1,226 @@
-/// Example: simple Schnorr proofs.
-///
-/// Schnorr proofs allow to prove knowledge of a secret key over a group $\mathbb{G}$ of prime order $p$ where the discrete logarithm problem is hard. In `spongefish`, we play with 3 data structures:
-///
-/// 1. `spongefish::DomainSeparator``
-/// The DomainSeparator describes the protocol.
-/// In the case of Schnorr proofs we have also some public information (the generator $P$ and the public key $X$).
-/// The protocol, roughly speaking is:
-///
-/// - P -> V: K, a commitment (point)
-/// - V -> P: c, a challenge (scalar)
-/// - P -> V: r, a response (scalar)
-///
-/// 2. `spongefish::ProverState`, describes the prover state. It contains the transcript, but not only:
-/// it also provides a CSPRNG and a reliable way of serializing elements into a proof, so that the prover does not have to worry about them.
-/// It can be instantiated via `DomainSeparator::to_prover_state()`.
-///
-/// 3. `spongefish::VerifierState`, describes the verifier state.
-/// It internally will read the transcript, and deserialize elements as requested making sure that they match with the domain separator.
-/// It can be used to verify a proof.
-use ark_ff::UniformRand;
-use ark_ec::{CurveGroup, PrimeGroup};
+//! Schnorr proofs of knowledge of a discrete logarithm, and the signatures they give through Fiat–Shamir.
+//! The proofs are `sigma::Schnorr` compiled by `sigma::prove` and `sigma::verify`, with the transcript layout of
+//! `sigma::SigmaDomainSeparator`; this module handles the statement and the proof bytes around them.
+//!
+//! Schnorr proofs allow to prove knowledge of a secret key over a group $\mathbb{G}$ of prime order $p$ where the discrete logarithm problem is hard. In `spongefish`, we play with 3 data structures:
+//!
+//! 1. `spongefish::DomainSeparator`
+//! The DomainSeparator describes the protocol.
+//! In the case of Schnorr proofs we have also some public information (the generator $P$ and the public key $X$).
+//! The protocol, roughly speaking is:
+//!
+//! - P -> V: K, a commitment (point)
+//! - V -> P: c, a challenge (scalar)
+//! - P -> V: r, a response (scalar)
+//!
+//! 2. `spongefish::ProverState`, describes the prover state. It contains the transcript, but not only:
+//! it also provides a CSPRNG and a reliable way of serializing elements into a proof, so that the prover does not have to worry about them.
+//! It can be instantiated via `DomainSeparator::to_prover_state()`.
+//!
+//! 3. `spongefish::VerifierState`, describes the verifier state.
+//! It internally will read the transcript, and deserialize elements as requested making sure that they match with the domain separator.
+//! It can be used to verify a proof.
+//!
+//! A signature on a message is a proof whose statement also holds a digest of the message,
+//! see `new_schnorr_signature`.
+use ark_ff::{UniformRand, Zero};
+use ark_ec::CurveGroup;
 use ark_std::rand::Rng;
-// use ark_std::UniformRand;
+use sha3::{Digest, Sha3_256};
 use spongefish::{codecs::arkworks_algebra::{
     CommonGroupToUnit, DomainSeparator, DuplexSpongeInterface, FieldDomainSeparator,
     FieldToUnitDeserialize, FieldToUnitSerialize, GroupDomainSeparator, GroupToUnitDeserialize,
     GroupToUnitSerialize, ProofError, ProofResult, ProverState, UnitToField, VerifierState
-}, keccak};
+}, ByteDomainSeparator, CommonUnitToBytes};
+use crate::sigma::{self, proof_length, read_proof, Schnorr, SigmaDomainSeparator, SigmaProtocol};
 
-/// Extend the domain separator with the Schnorr protocol.
-trait SchnorrDomainSeparator<G: CurveGroup> {
-    /// Shortcut: create a new schnorr proof with statement + proof.
-    fn new_schnorr_proof(domsep: &str) -> Self;
+/// Bytes of the digest a signed message is absorbed as, so that one domain separator serves messages of any length.
+pub const MESSAGE_DIGEST_BYTES: usize = 32;
 
-    /// Add the statement of the Schnorr proof
-    fn add_schnorr_statement(self) -> Self;
-    /// Add the Schnorr protocol to the domain separator.
-    fn add_schnorr_domsep(self) -> Self;
+/// The transcript layout only depends on the group, so any statement describes it.
+fn layout<G: CurveGroup>() -> Schnorr<G> {
+    Schnorr { generator: G::generator(), public_key: G::generator() }
 }
 
-impl<G, H> SchnorrDomainSeparator<G> for DomainSeparator<H>
+/// Domain separator for `prove`/`verify`: `sigma::SigmaDomainSeparator::new_sigma_proof` for `Schnorr`.
+pub fn new_schnorr_proof<G, H>(domsep: &str) -> DomainSeparator<H>
 where
     G: CurveGroup,
     H: DuplexSpongeInterface,
-    Self: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>,
 {
-    fn new_schnorr_proof(domsep: &str) -> Self {
-        Self::new(domsep)
-            .add_schnorr_statement()
-            .add_schnorr_domsep()
-    }
-
-    fn add_schnorr_statement(self) -> Self {
-        self.add_points(1, "generator (P)")
-            .add_points(1, "public key (X)")
-            .ratchet()
-    }
+    DomainSeparator::new_sigma_proof(domsep, &layout::<G>())
+}
 
-    fn add_schnorr_domsep(self) -> Self {
-        self.add_points(1, "commitment (K)")
-            .challenge_scalars(2, "challenge (c)")
-            // .challenge_scalars(1, "challenge (c1)")
-            .add_scalars(1, "response (r)")
-    }
+/// Domain separator for `sign`/`verify_signature`: the proof of `new_schnorr_proof` with the message digest
+/// absorbed between the statement and the commitment.
+pub fn new_schnorr_signature<G, H>(domsep: &str) -> DomainSeparator<H>
+where
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    DomainSeparator<H>: GroupDomainSeparator<G> + FieldDomainSeparator<G::ScalarField>,
+{
+    let ds = DomainSeparator::new(domsep).add_sigma_statement(&layout::<G>());
+    ds.add_bytes(MESSAGE_DIGEST_BYTES, "message digest (m)")
+        .ratchet()
+        .add_sigma_domsep(&layout::<G>())
 }
 
 /// The key generation algorithm otuputs
 /// a secret key `sk` in $\mathbb{Z}_p$
 /// and its respective public key `pk` in $\mathbb{G}$.
-fn keygen<G: CurveGroup, R: Rng + ?Sized>(rnd: &mut R) -> (G::ScalarField, G) {
+pub fn keygen<G: CurveGroup, R: Rng + ?Sized>(rnd: &mut R) -> (G::ScalarField, G) {
     let sk = G::ScalarField::rand(rnd);
     let pk = G::generator() * sk;
     (sk, pk)
 }
 
+fn message_digest(message: &[u8]) -> [u8; MESSAGE_DIGEST_BYTES] {
+    Sha3_256::digest(message).into()
+}
+
+/// Starts the prover transcript and adds the statement to it, followed for signatures by the digest of `message`.
+fn prover_state<H, G>(io: &DomainSeparator<H>, statement: &Schnorr<G>, message: Option<&[u8]>) -> ProofResult<ProverState<H>>
+where
+    H: DuplexSpongeInterface,
+    G: CurveGroup,
+    ProverState<H>: CommonGroupToUnit<G>,
+{
+    let mut prover_state = io.to_prover_state();
+    prover_state.public_points(&statement.statement())?;
+    prover_state.ratchet()?;
+    if let Some(message) = message {
+        prover_state.public_bytes(&message_digest(message))?;
+        prover_state.ratchet()?;
+    }
+    Ok(prover_state)
+}
+
+/// Starts the verifier transcript of `proof` like `prover_state`.
+/// Fails unless `proof` is exactly `sigma::proof_length` bytes: the transcript reads what the domain separator
+/// asks for and would not notice bytes left over after the response.
+fn verifier_state<'a, G, H>(io: &DomainSeparator<H>, statement: &Schnorr<G>, message: Option<&[u8]>, proof: &'a [u8]) -> ProofResult<VerifierState<'a, H>>
+where
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    for<'b> VerifierState<'b, H>: CommonGroupToUnit<G>,
+{
+    if proof.len() != proof_length(statement) {
+        return Err(ProofError::SerializationError);
+    }
+    let mut verifier_state = io.to_verifier_state(proof);
+    verifier_state.public_points(&statement.statement())?;
+    verifier_state.ratchet()?;
+    if let Some(message) = message {
+        verifier_state.public_bytes(&message_digest(message))?;
+        verifier_state.ratchet()?;
+    }
+    Ok(verifier_state)
+}
+
 /// The prove algorithm takes as input
-/// - the prover state `ProverState`, that has access to a random oracle `H` and can absorb/squeeze elements from the group `G`.
+/// - the domain separator `io`, from `new_schnorr_proof`.
 /// - The generator `P` in the group.
 /// - the secret key $x \in \mathbb{Z}_p$
+///
 /// It returns a zero-knowledge proof of knowledge of `x` as a sequence of bytes.
 #[allow(non_snake_case)]
-fn prove<H, G>(
-    // the hash function `H` works over bytes.
-    // Algebraic hashes over a particular domain can be denoted with an additional type argument implementing `spongefish::Unit`.
-    prover_state: &mut ProverState<H>,
-    // the generator
-    P: G,
-    // the secret key
-    x: G::ScalarField,
-) -> ProofResult<&[u8]>
+pub fn prove<H, G>(io: &DomainSeparator<H>, P: G, x: G::ScalarField) -> ProofResult<Vec<u8>>
 where
     H: DuplexSpongeInterface,
     G: CurveGroup,
-    ProverState<H>: GroupToUnitSerialize<G> + UnitToField<G::ScalarField>,
+    ProverState<H>: GroupToUnitSerialize<G> + UnitToField<G::ScalarField> + FieldToUnitSerialize<G::ScalarField>
+        + CommonGroupToUnit<G>,
 {
-    // `ProverState` types implement a cryptographically-secure random number generator that is tied to the protocol transcript
-    // and that can be accessed via the `rng()` function.
-    let k = G::ScalarField::rand(prover_state.rng());
-    let K = P * k;
-
-    // Add a sequence of points to the protocol transcript.
-    // An error is returned in case of failed serialization, or inconsistencies with the domain separator provided (see below).
-    prover_state.add_points(&[K])?;
-
-    // Fetch a challenge from the current transcript state.
-    let [_] = prover_state.challenge_scalars()?;
-    let [c] = prover_state.challenge_scalars()?;
-
-    let r = k + c * x;
-    // Add a sequence of scalar elements to the protocol transcript.
-    prover_state.add_scalars(&[r])?;
-
-    // Output the current protocol transcript as a sequence of bytes.
-    Ok(prover_state.narg_string())
+    let statement = Schnorr { generator: P, public_key: P * x };
+    let mut prover_state = prover_state(io, &statement, None)?;
+    Ok(sigma::prove(&mut prover_state, &statement, &x)?.to_vec())
 }
 
 /// The verify algorithm takes as input
-/// - the verifier state `VerifierState`, that has access to a random oracle `H` and can deserialize/squeeze elements from the group `G`.
-/// - the secret key `witness`
-/// It returns a zero-knowledge proof of knowledge of `witness` as a sequence of bytes.
+/// - the domain separator `io`, from `new_schnorr_proof`.
+/// - the generator `P` and the public key `X` of the statement.
+/// - the `proof` output by `prove`.
+///
+/// It accepts if `proof` proves knowledge of the discrete logarithm of `X`.
+#[allow(non_snake_case)]
+pub fn verify<G, H>(io: &DomainSeparator<H>, P: G, X: G, proof: &[u8]) -> ProofResult<()>
+where
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<G>
+        + FieldToUnitDeserialize<G::ScalarField>
+        + UnitToField<G::ScalarField>
+        + CommonGroupToUnit<G>,
+{
+    let statement = Schnorr { generator: P, public_key: X };
+    sigma::verify(&mut verifier_state(io, &statement, None, proof)?, &statement)
+}
+
+/// Verifies many `prove` proofs for the same generator `P` at once, each given with its public key.
+/// The verification equations are combined with random weights from `rng` into a single multi-scalar
+/// multiplication, which is zero for valid proofs and nonzero except with probability `1/p` otherwise.
 #[allow(non_snake_case)]
-fn verify<G, H>(
-    // `ArkGroupMelin` contains the veirifier state, including the messages currently read. In addition, it is aware of the group `G`
-    // from which it can serialize/deserialize elements.
-    verifier_state: &mut VerifierState<H>,
-    // The group generator `P``
+pub fn batch_verify<G, H, R>(
+    io: &DomainSeparator<H>,
+    rng: &mut R,
     P: G,
-    // The public key `X`
-    X: G,
+    instances: &[(&[u8], G)],
 ) -> ProofResult<()>
 where
     G: CurveGroup,
     H: DuplexSpongeInterface,
+    R: Rng + ?Sized,
     for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<G>
         + FieldToUnitDeserialize<G::ScalarField>
-        + UnitToField<G::ScalarField>,
+        + UnitToField<G::ScalarField>
+        + CommonGroupToUnit<G>,
 {
-    // Read the protocol from the transcript.
-    // [[Side note:
-    // The method `next_points` internally performs point validation.
-    // Another implementation that does not use spongefish might choose not to validate the point here, but only validate the public-key.
-    // This leads to different errors to be returned: here the proof fails with SerializationError, whereas the other implementation would fail with InvalidProof.
-    // ]]
-    let [K] = verifier_state.next_points().unwrap();
-    let [_] = verifier_state.challenge_scalars().unwrap();
-    let [c] = verifier_state.challenge_scalars().unwrap();
-    let [r]: [G::ScalarField; 1] = verifier_state.next_scalars().unwrap();
-
-    // Check the verification equation, otherwise return a verification error.
-    // The type ProofError is an enum that can report:
-    // - InvalidProof: the proof is not valid
-    // - InvalidIO: the transcript does not match the domain separator
-    // - SerializationError: there was an error serializing/deserializing an element
-    if P * r == K + X * c {
+    // sum_i rho_i * (r_i P - K_i - c_i X_i) = 0
+    let mut bases = vec![P];
+    let mut scalars = vec![G::ScalarField::zero()];
+    for (proof, X) in instances {
+        let statement = Schnorr { generator: P, public_key: *X };
+        let (K, c, r) = read_proof(&mut verifier_state(io, &statement, None, proof)?, &statement)?;
+        let rho = G::ScalarField::rand(rng);
+        scalars[0] += rho * r[0];
+        bases.extend([K[0], *X]);
+        scalars.extend([-rho, -rho * c]);
+    }
+    let combination = G::msm(&G::normalize_batch(&bases), &scalars).map_err(|_| ProofError::InvalidProof)?;
+    if combination.is_zero() {
         Ok(())
     } else {
         Err(ProofError::InvalidProof)
     }
-
-    // From here, another proof can be verified using the same  instance
-    // and proofs can be composed. The transcript holds the whole proof,
 }
 
+/// Signs `message` with the secret key `x`: a proof of knowledge of `x` whose statement holds the message digest.
+/// `io` must come from `new_schnorr_signature`.
 #[allow(non_snake_case)]
-pub fn spongefish_test() {
-    // Instantiate the group and the random oracle:
-    // Set the group:
-    type G = ark_ed_on_bls12_381::EdwardsProjective;
-    // Set the hash function (commented out other valid choices):
-    // type H = spongefish::hash::Keccak;
-    type H = spongefish::duplex_sponge::legacy::DigestBridge<sha3::Keccak224>;
-    // type H = spongefish::hash::legacy::DigestBridge<sha2::Sha256>;
-
-    // Set up the IO for the protocol transcript with domain separator "spongefish::examples::schnorr"
-    let io: DomainSeparator<H> =
-        SchnorrDomainSeparator::<G>::new_schnorr_proof("spongefish::example");
-
-    // Set up the elements to prove
-    let P = G::generator();
-
-    let mut rnd = ark_std::test_rng();
-    let (x, X) = keygen(&mut rnd);
-
-    // Create the prover transcript, add the statement to it, and then invoke the prover.
-    let mut prover_state = io.to_prover_state();
-    prover_state.public_points(&[P, P * x]).unwrap();
-    prover_state.ratchet().unwrap();
-    let proof = prove(&mut prover_state, P, x).expect("Invalid proof");
-
-    // Print out the hex-encoded schnorr proof.
-    println!("Here's a Schnorr signature:\n{}", hex::encode(proof));
+pub fn sign<G, H>(io: &DomainSeparator<H>, P: G, x: G::ScalarField, message: &[u8]) -> ProofResult<Vec<u8>>
+where
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    ProverState<H>: GroupToUnitSerialize<G> + UnitToField<G::ScalarField> + FieldToUnitSerialize<G::ScalarField>
+        + CommonGroupToUnit<G>,
+{
+    let statement = Schnorr { generator: P, public_key: P * x };
+    let mut prover_state = prover_state(io, &statement, Some(message))?;
+    Ok(sigma::prove(&mut prover_state, &statement, &x)?.to_vec())
+}
 
-    // Verify the proof: create the verifier transcript, add the statement to it, and invoke the verifier.
-    let mut verifier_state = io.to_verifier_state(proof);
-    verifier_state.public_points(&[P, X]).unwrap();
-    verifier_state.ratchet().unwrap();
-    verify(&mut verifier_state, P, X).expect("Invalid proof");
-}
\ No newline at end of file
+/// Verifies a `sign` signature on `message` under the public key `X`.
+#[allow(non_snake_case)]
+pub fn verify_signature<G, H>(io: &DomainSeparator<H>, P: G, X: G, message: &[u8], signature: &[u8]) -> ProofResult<()>
+where
+    G: CurveGroup,
+    H: DuplexSpongeInterface,
+    for<'a> VerifierState<'a, H>: GroupToUnitDeserialize<G>
+        + FieldToUnitDeserialize<G::ScalarField>
+        + UnitToField<G::ScalarField>
+        + CommonGroupToUnit<G>,
+{
+    let statement = Schnorr { generator: P, public_key: X };
+    sigma::verify(&mut verifier_state(io, &statement, Some(message), signature)?, &statement)
+}